@@ -0,0 +1,203 @@
+use crate::error::Error;
+use allocator::Allocator;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, marker::PhantomData};
+
+// The smallest range IDs are permuted within.
+const MIN_BITS: u32 = 8;
+// Rounds of the Feistel network permuting IDs.
+const ROUNDS: u64 = 8;
+
+/// An allocator that hands out IDs in a keyed pseudorandom order.
+///
+/// With sequential IDs, anyone watching the underlying storage can tell which objects were
+/// created together (e.g. a data object and its `Khf`) and in what order. Here the `n`th ID is a
+/// keyed permutation of `n` within the power-of-two range holding it, so the order is hidden
+/// while IDs stay below twice the number ever allocated. Dense IDs keep key schemes indexed by
+/// them, like the master `Khf`, compact. Freed IDs are reused in random order.
+#[derive(Serialize, Deserialize)]
+pub struct RandomizedAllocator<R> {
+    key: u64,
+    next: u64,
+    allocated: BTreeSet<u64>,
+    free: BTreeSet<u64>,
+    #[serde(skip)]
+    pd: PhantomData<R>,
+}
+
+impl<R> RandomizedAllocator<R>
+where
+    R: RngCore + CryptoRng + Default,
+{
+    pub fn new() -> Self {
+        Self {
+            key: R::default().next_u64(),
+            next: 0,
+            allocated: BTreeSet::new(),
+            free: BTreeSet::new(),
+            pd: PhantomData,
+        }
+    }
+}
+
+impl<R> RandomizedAllocator<R> {
+    // Maps `index` to an ID in the same power-of-two range, cycle-walking a Feistel network
+    // that's wide enough to cover the range.
+    fn permute(&self, index: u64) -> u64 {
+        let bits = if index < 1 << MIN_BITS {
+            MIN_BITS
+        } else {
+            u64::BITS - 1 - index.leading_zeros()
+        };
+        let base = index & !((1 << bits) - 1);
+        let half = bits.div_ceil(2);
+
+        let mut offset = index - base;
+        loop {
+            offset = self.feistel(offset, half);
+            if offset >> bits == 0 {
+                return base + offset;
+            }
+        }
+    }
+
+    fn feistel(&self, x: u64, half: u32) -> u64 {
+        let mask = (1 << half) - 1;
+        let (mut left, mut right) = (x >> half, x & mask);
+        for round in 0..ROUNDS {
+            let f = mix(mix(self.key ^ round) ^ right) & mask;
+            (left, right) = (right, left ^ f);
+        }
+        (left << half) | right
+    }
+}
+
+// The SplitMix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl<R> Default for RandomizedAllocator<R>
+where
+    R: RngCore + CryptoRng + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> Allocator for RandomizedAllocator<R>
+where
+    R: RngCore + CryptoRng + Default,
+{
+    type Id = u64;
+    type Error = Error;
+
+    fn alloc(&mut self) -> Result<Self::Id, Self::Error> {
+        // Reuse a freed ID if there is one.
+        if !self.free.is_empty() {
+            let n = R::default().next_u64() % self.free.len() as u64;
+            let id = *self.free.iter().nth(n as usize).unwrap();
+            self.free.remove(&id);
+            self.allocated.insert(id);
+            return Ok(id);
+        }
+
+        // Reserved IDs are skipped over when they come up.
+        loop {
+            if self.next == u64::MAX {
+                return Err(Error::Alloc);
+            }
+            let id = self.permute(self.next);
+            self.next += 1;
+            if self.allocated.insert(id) {
+                return Ok(id);
+            }
+        }
+    }
+
+    fn dealloc(&mut self, id: Self::Id) -> Result<(), Self::Error> {
+        if self.allocated.remove(&id) {
+            self.free.insert(id);
+            Ok(())
+        } else {
+            Err(Error::Dealloc)
+        }
+    }
+
+    fn reserve(&mut self, id: Self::Id) -> Result<(), Self::Error> {
+        if self.allocated.insert(id) {
+            self.free.remove(&id);
+            Ok(())
+        } else {
+            Err(Error::Alloc)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use rand::rngs::ThreadRng;
+
+    #[test]
+    fn unique() -> Result<()> {
+        let mut alloc = RandomizedAllocator::<ThreadRng>::new();
+        alloc.reserve(0)?;
+
+        let ids: Vec<u64> = (0..1000).map(|_| alloc.alloc()).collect::<Result<_, _>>()?;
+        let unique: BTreeSet<u64> = ids.iter().copied().collect();
+        assert_eq!(unique.len(), 1000);
+        assert!(!unique.contains(&0));
+
+        // IDs are dense, but not handed out in order.
+        assert!(ids.iter().all(|&id| id < 2048));
+        assert!(ids.windows(2).any(|pair| pair[1] < pair[0]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn permutation() {
+        let alloc = RandomizedAllocator::<ThreadRng>::new();
+
+        for range in [0..1 << MIN_BITS, 1 << 11..1 << 12] {
+            let ids: BTreeSet<u64> = range.clone().map(|index| alloc.permute(index)).collect();
+            assert_eq!(ids, range.collect());
+        }
+    }
+
+    #[test]
+    fn reserve_dealloc() -> Result<()> {
+        let mut alloc = RandomizedAllocator::<ThreadRng>::new();
+
+        alloc.reserve(7)?;
+        assert!(alloc.reserve(7).is_err());
+        alloc.dealloc(7)?;
+        assert!(alloc.dealloc(7).is_err());
+
+        // Freed IDs are reused before new ones are drawn.
+        assert_eq!(alloc.alloc()?, 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn serde() -> Result<()> {
+        let mut alloc = RandomizedAllocator::<ThreadRng>::new();
+        let id = alloc.alloc()?;
+
+        let mut alloc: RandomizedAllocator<ThreadRng> =
+            bincode::deserialize(&bincode::serialize(&alloc)?)?;
+        assert!(alloc.reserve(id).is_err());
+
+        // The permutation is the same after a reload, so IDs aren't handed out twice.
+        assert_ne!(alloc.alloc()?, id);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use super::Enclave;
+use crate::error::Error;
+use embedded_io::{
+    adapters::FromStd,
+    blocking::{Read, Seek, Write},
+    SeekFrom,
+};
+use std::fs::File;
+
+// Default number of bytes that can be sealed.
+const DEFAULT_CAPACITY: usize = 4096;
+
+// Layout: the counter, followed by two slots. Each slot holds a sequence number, the length of
+// the sealed root, the root itself (padded to the capacity), and a checksum over all of that.
+const COUNTER_OFFSET: u64 = 0;
+const SLOTS_OFFSET: u64 = 8;
+const NUM_SLOTS: u64 = 2;
+const HEADER_SIZE: usize = 16;
+const CHECKSUM_SIZE: usize = 8;
+
+// FNV-1a. This only needs to catch torn writes, not tampering.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// An `IO` whose writes can be forced out to the device, which `flush()` alone doesn't
+/// guarantee.
+pub trait SyncIo {
+    fn sync(&mut self) -> Result<(), Error>;
+}
+
+impl SyncIo for FromStd<File> {
+    fn sync(&mut self) -> Result<(), Error> {
+        self.inner().sync_all().map_err(|_| Error::Io)
+    }
+}
+
+struct Slot {
+    index: u64,
+    seq: u64,
+    root: Vec<u8>,
+}
+
+/// A reference `Enclave` backed by anything that can be read, written and seeked, such as a
+/// file on a trusted device.
+///
+/// Roots are sealed alternately into one of two slots, so a torn write or a crash mid-commit
+/// never clobbers the last committed root. The roots are stored in the clear and the counter
+/// lives alongside them, so this is only as secure as the underlying device is.
+pub struct FileEnclave<IO> {
+    io: IO,
+    capacity: usize,
+}
+
+impl<IO> FileEnclave<IO>
+where
+    IO: Read + Write + Seek + SyncIo,
+{
+    pub fn new(io: IO) -> Self {
+        Self::with_capacity(io, DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(io: IO, capacity: usize) -> Self {
+        Self { io, capacity }
+    }
+
+    /// Consumes the enclave, returning the underlying `IO`.
+    pub fn into_inner(self) -> IO {
+        self.io
+    }
+
+    fn slot_size(&self) -> usize {
+        HEADER_SIZE + self.capacity + CHECKSUM_SIZE
+    }
+
+    fn slot_offset(&self, index: u64) -> u64 {
+        SLOTS_OFFSET + index * self.slot_size() as u64
+    }
+
+    // Reads a slot, returning `None` if it's empty, erased, or torn.
+    fn read_slot(&mut self, index: u64) -> Result<Option<Slot>, Error> {
+        let mut buf = vec![0; self.slot_size()];
+
+        self.io
+            .seek(SeekFrom::Start(self.slot_offset(index)))
+            .map_err(|_| Error::Io)?;
+
+        let mut total = 0;
+        while total < buf.len() {
+            let n = self.io.read(&mut buf[total..]).map_err(|_| Error::Io)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            total += n;
+        }
+
+        let (body, sum) = buf.split_at(HEADER_SIZE + self.capacity);
+        if checksum(body) != u64::from_le_bytes(sum.try_into().unwrap()) {
+            return Ok(None);
+        }
+
+        let seq = u64::from_le_bytes(body[..8].try_into().unwrap());
+        let len = u64::from_le_bytes(body[8..16].try_into().unwrap()) as usize;
+        if len > self.capacity {
+            return Ok(None);
+        }
+
+        Ok(Some(Slot {
+            index,
+            seq,
+            root: body[HEADER_SIZE..HEADER_SIZE + len].to_vec(),
+        }))
+    }
+
+    fn write_slot(&mut self, index: u64, buf: &[u8]) -> Result<(), Error> {
+        self.io
+            .seek(SeekFrom::Start(self.slot_offset(index)))
+            .map_err(|_| Error::Io)?;
+        self.io.write_all(buf).map_err(|_| Error::Io)?;
+        self.io.flush().map_err(|_| Error::Io)?;
+
+        // Erased slots in particular must stay erased across a power loss.
+        self.io.sync()
+    }
+
+    fn erase_slot(&mut self, index: u64) -> Result<(), Error> {
+        self.write_slot(index, &vec![0; self.slot_size()])
+    }
+
+    // Returns the valid slots, newest first.
+    fn slots(&mut self) -> Result<Vec<Slot>, Error> {
+        let mut slots = vec![];
+        for index in 0..NUM_SLOTS {
+            if let Some(slot) = self.read_slot(index)? {
+                slots.push(slot);
+            }
+        }
+        slots.sort_by(|a, b| b.seq.cmp(&a.seq));
+        Ok(slots)
+    }
+}
+
+impl<IO> Enclave for FileEnclave<IO>
+where
+    IO: Read + Write + Seek + SyncIo,
+{
+    type Error = Error;
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn seal_root(&mut self, root: &[u8]) -> Result<(), Self::Error> {
+        if root.len() > self.capacity {
+            return Err(Error::EnclaveCapacity);
+        }
+
+        // Always write to the slot that doesn't hold the newest root.
+        let slots = self.slots()?;
+        let (index, seq) = match slots.first() {
+            Some(newest) => ((newest.index + 1) % NUM_SLOTS, newest.seq + 1),
+            None => (0, 1),
+        };
+
+        let mut buf = Vec::with_capacity(self.slot_size());
+        buf.extend_from_slice(&seq.to_le_bytes());
+        buf.extend_from_slice(&(root.len() as u64).to_le_bytes());
+        buf.extend_from_slice(root);
+        buf.resize(HEADER_SIZE + self.capacity, 0);
+        buf.extend_from_slice(&checksum(&buf).to_le_bytes());
+
+        self.write_slot(index, &buf)
+    }
+
+    fn unseal_root(&mut self) -> Result<Vec<u8>, Self::Error> {
+        self.slots()?
+            .into_iter()
+            .next()
+            .map(|slot| slot.root)
+            .ok_or(Error::Enclave)
+    }
+
+    fn unseal_roots(&mut self) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(self.slots()?.into_iter().map(|slot| slot.root).collect())
+    }
+
+    fn retire_root(&mut self) -> Result<(), Self::Error> {
+        for slot in self.slots()?.into_iter().skip(1) {
+            self.erase_slot(slot.index)?;
+        }
+        Ok(())
+    }
+
+    fn discard_root(&mut self) -> Result<(), Self::Error> {
+        if let Some(newest) = self.slots()?.first() {
+            self.erase_slot(newest.index)?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Self::Error> {
+        self.io.sync()
+    }
+
+    fn counter(&mut self) -> Result<u64, Self::Error> {
+        let mut buf = [0; 8];
+
+        self.io
+            .seek(SeekFrom::Start(COUNTER_OFFSET))
+            .map_err(|_| Error::Io)?;
+
+        // A fresh enclave hasn't had its counter written yet.
+        let n = self.io.read(&mut buf).map_err(|_| Error::Io)?;
+        if n < buf.len() {
+            return Ok(0);
+        }
+
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn increment_counter(&mut self) -> Result<u64, Self::Error> {
+        let counter = self.counter()?.checked_add(1).ok_or(Error::Enclave)?;
+
+        self.io
+            .seek(SeekFrom::Start(COUNTER_OFFSET))
+            .map_err(|_| Error::Io)?;
+        self.io
+            .write_all(&counter.to_le_bytes())
+            .map_err(|_| Error::Io)?;
+        self.io.flush().map_err(|_| Error::Io)?;
+
+        Ok(counter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use tempfile::tempfile;
+
+    #[test]
+    fn seal_unseal() -> Result<()> {
+        let mut enclave = FileEnclave::new(FromStd::new(tempfile()?));
+
+        enclave.seal_root(&[1; 32])?;
+        enclave.increment_counter()?;
+        enclave.seal_root(&[2; 32])?;
+        enclave.sync()?;
+
+        assert_eq!(enclave.unseal_root()?, vec![2; 32]);
+        assert_eq!(enclave.counter()?, 1);
+
+        Ok(())
+    }
+
+    // An uncommitted root sits alongside the committed one until it's retired or discarded.
+    #[test]
+    fn retire_discard() -> Result<()> {
+        let mut enclave = FileEnclave::new(FromStd::new(tempfile()?));
+
+        enclave.seal_root(&[1; 32])?;
+        enclave.retire_root()?;
+        enclave.seal_root(&[2; 32])?;
+        assert_eq!(enclave.unseal_roots()?, vec![vec![2; 32], vec![1; 32]]);
+
+        enclave.discard_root()?;
+        assert_eq!(enclave.unseal_roots()?, vec![vec![1; 32]]);
+
+        // The next root must not overwrite the committed one.
+        enclave.seal_root(&[3; 32])?;
+        enclave.retire_root()?;
+        assert_eq!(enclave.unseal_roots()?, vec![vec![3; 32]]);
+
+        Ok(())
+    }
+
+    // A torn write leaves the previous root intact.
+    #[test]
+    fn torn_write() -> Result<()> {
+        let mut enclave = FileEnclave::new(FromStd::new(tempfile()?));
+
+        enclave.seal_root(&[1; 32])?;
+        enclave.seal_root(&[2; 32])?;
+
+        let offset = enclave.slot_offset(1) + HEADER_SIZE as u64;
+        enclave.io.seek(SeekFrom::Start(offset))?;
+        enclave.io.write_all(&[0xff; 8])?;
+
+        assert_eq!(enclave.unseal_root()?, vec![1; 32]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn counter() -> Result<()> {
+        let mut enclave = FileEnclave::new(FromStd::new(tempfile()?));
+
+        assert_eq!(enclave.counter()?, 0);
+        assert_eq!(enclave.increment_counter()?, 1);
+        assert_eq!(enclave.increment_counter()?, 2);
+        assert_eq!(enclave.counter()?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn capacity() -> Result<()> {
+        let mut enclave = FileEnclave::with_capacity(FromStd::new(tempfile()?), 16);
+        assert!(matches!(
+            enclave.seal_root(&[0; 32]),
+            Err(Error::EnclaveCapacity)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::error::Error;
+use argon2::{Algorithm, Argon2, Params, Version};
+
+// Size of the random salts fed to the KDF.
+pub(crate) const SALT_SIZE: usize = 16;
+
+// Size of the verifiers used to detect wrong credentials.
+pub(crate) const VERIFIER_SIZE: usize = 32;
+
+// Parameters for wrapping roots under a key that's already uniformly random, which doesn't need
+// a costly KDF.
+pub(crate) const ROOT_KDF_PARAMS: KdfParams = KdfParams {
+    m_cost: 8,
+    t_cost: 1,
+    p_cost: 1,
+};
+
+/// Cost parameters for the memory-hard (Argon2id) KDF used to derive keys from credentials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB.
+    pub m_cost: u32,
+    /// Number of iterations.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Fills `out` with key material derived from `secret` and `salt`.
+pub(crate) fn derive(
+    secret: &[u8],
+    salt: &[u8],
+    params: &KdfParams,
+    out: &mut [u8],
+) -> Result<(), Error> {
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(out.len()))
+        .map_err(|_| Error::Kdf)?;
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(secret, salt, out)
+        .map_err(|_| Error::Kdf)
+}
+
+/// Compares two byte strings in constant time (with respect to their contents).
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// XORs `pad` into `buf`.
+pub(crate) fn xor(buf: &mut [u8], pad: &[u8]) {
+    for (b, p) in buf.iter_mut().zip(pad) {
+        *b ^= p;
+    }
+}
@@ -0,0 +1,383 @@
+use super::{
+    kdf::{self, KdfParams, ROOT_KDF_PARAMS, SALT_SIZE, VERIFIER_SIZE},
+    Enclave,
+};
+use crate::error::Error;
+use rand::{rngs::ThreadRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+// Size of the volume key that the key slots wrap.
+const VOLUME_KEY_SIZE: usize = 32;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct KeySlot {
+    id: u64,
+    label: String,
+    salt: [u8; SALT_SIZE],
+    verifier: [u8; VERIFIER_SIZE],
+    wrapped_volume_key: Vec<u8>,
+}
+
+// What gets sealed in the inner enclave.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Header {
+    slots: Vec<KeySlot>,
+    nonce: [u8; SALT_SIZE],
+    wrapped_root: Vec<u8>,
+}
+
+/// Describes a key slot, as returned by `KeySlotEnclave::slots()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySlotInfo {
+    pub id: u64,
+    pub label: String,
+}
+
+/// An `Enclave` with several independent credentials (passphrases, recovery keys, machine
+/// keys), any of which can unlock it, in the style of LUKS.
+///
+/// Each key slot wraps a stable volume key under a key derived from its credential using a
+/// memory-hard KDF. Roots are in turn wrapped under the volume key, so credentials can be added
+/// and revoked without touching the root. The slots and the wrapped root are sealed together as
+/// a header in an inner `Enclave`; revoking a slot reseals the header without it and securely
+/// erases the previous header.
+pub struct KeySlotEnclave<S> {
+    inner: S,
+    header: Header,
+    volume_key: Zeroizing<Vec<u8>>,
+    params: KdfParams,
+}
+
+impl<S> KeySlotEnclave<S>
+where
+    S: Enclave,
+{
+    /// Wraps a fresh `inner` enclave, with a single key slot for `credential`.
+    pub fn create(
+        inner: S,
+        label: &str,
+        credential: &[u8],
+        params: KdfParams,
+    ) -> Result<Self, Error> {
+        let mut volume_key = Zeroizing::new(vec![0; VOLUME_KEY_SIZE]);
+        ThreadRng::default().fill_bytes(&mut volume_key);
+
+        let mut enclave = Self {
+            inner,
+            header: Header::default(),
+            volume_key,
+            params,
+        };
+        let slot = enclave.new_slot(0, label, credential)?;
+        enclave.header.slots.push(slot);
+
+        Ok(enclave)
+    }
+
+    /// Wraps an `inner` enclave that already holds a header, unlocking it with whichever key slot
+    /// `credential` belongs to.
+    pub fn unlock(mut inner: S, credential: &[u8], params: KdfParams) -> Result<Self, Error> {
+        let blob = inner.unseal_root().map_err(|_| Error::Enclave)?;
+        let header: Header = bincode::deserialize(&blob)?;
+
+        for slot in &header.slots {
+            let mut derived =
+                Zeroizing::new(vec![0; VERIFIER_SIZE + slot.wrapped_volume_key.len()]);
+            kdf::derive(credential, &slot.salt, &params, &mut derived)?;
+            let (verifier, pad) = derived.split_at(VERIFIER_SIZE);
+
+            if kdf::ct_eq(verifier, &slot.verifier) {
+                let mut volume_key = Zeroizing::new(slot.wrapped_volume_key.clone());
+                kdf::xor(&mut volume_key, pad);
+
+                return Ok(Self {
+                    inner,
+                    header,
+                    volume_key,
+                    params,
+                });
+            }
+        }
+
+        Err(Error::NoKeySlot)
+    }
+
+    /// Adds a key slot for `credential`, returning its ID.
+    pub fn add_slot(&mut self, label: &str, credential: &[u8]) -> Result<u64, Error> {
+        let id = self
+            .header
+            .slots
+            .iter()
+            .map(|slot| slot.id + 1)
+            .max()
+            .unwrap_or(0);
+
+        let slot = self.new_slot(id, label, credential)?;
+        self.header.slots.push(slot);
+        self.reseal_header()?;
+
+        Ok(id)
+    }
+
+    /// Revokes a key slot. The previous header, which still holds the slot, is securely erased.
+    /// The last key slot can't be revoked, since that would lock everyone out.
+    pub fn revoke_slot(&mut self, id: u64) -> Result<(), Error> {
+        let index = self
+            .header
+            .slots
+            .iter()
+            .position(|slot| slot.id == id)
+            .ok_or(Error::NoKeySlot)?;
+
+        if self.header.slots.len() == 1 {
+            return Err(Error::LastKeySlot);
+        }
+
+        self.header.slots.remove(index);
+        self.reseal_header()
+    }
+
+    /// Lists the key slots.
+    pub fn slots(&self) -> Vec<KeySlotInfo> {
+        self.header
+            .slots
+            .iter()
+            .map(|slot| KeySlotInfo {
+                id: slot.id,
+                label: slot.label.clone(),
+            })
+            .collect()
+    }
+
+    /// Consumes the enclave, returning the inner `Enclave`.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn new_slot(&self, id: u64, label: &str, credential: &[u8]) -> Result<KeySlot, Error> {
+        let mut salt = [0; SALT_SIZE];
+        ThreadRng::default().fill_bytes(&mut salt);
+
+        let mut derived = Zeroizing::new(vec![0; VERIFIER_SIZE + self.volume_key.len()]);
+        kdf::derive(credential, &salt, &self.params, &mut derived)?;
+        let (verifier, pad) = derived.split_at(VERIFIER_SIZE);
+
+        let mut wrapped_volume_key = self.volume_key.to_vec();
+        kdf::xor(&mut wrapped_volume_key, pad);
+
+        Ok(KeySlot {
+            id,
+            label: label.to_string(),
+            salt,
+            verifier: verifier.try_into().unwrap(),
+            wrapped_volume_key,
+        })
+    }
+
+    // Seals the current header and securely erases the previous one.
+    fn reseal_header(&mut self) -> Result<(), Error> {
+        let blob = bincode::serialize(&self.header)?;
+        self.inner.seal_root(&blob).map_err(|_| Error::Enclave)?;
+        self.inner.retire_root().map_err(|_| Error::Enclave)
+    }
+
+    fn unwrap_root(&self, blob: &[u8]) -> Result<Vec<u8>, Error> {
+        let header: Header = bincode::deserialize(blob)?;
+
+        let mut pad = Zeroizing::new(vec![0; header.wrapped_root.len()]);
+        kdf::derive(&self.volume_key, &header.nonce, &ROOT_KDF_PARAMS, &mut pad)?;
+
+        let mut root = header.wrapped_root;
+        kdf::xor(&mut root, &pad);
+        Ok(root)
+    }
+}
+
+impl<S> Enclave for KeySlotEnclave<S>
+where
+    S: Enclave,
+{
+    type Error = Error;
+
+    fn capacity(&self) -> usize {
+        let overhead = bincode::serialized_size(&Header {
+            wrapped_root: vec![],
+            ..self.header.clone()
+        })
+        .unwrap_or(u64::MAX);
+
+        self.inner.capacity().saturating_sub(overhead as usize)
+    }
+
+    fn seal_root(&mut self, root: &[u8]) -> Result<(), Self::Error> {
+        if root.len() > self.capacity() {
+            return Err(Error::EnclaveCapacity);
+        }
+
+        let mut nonce = [0; SALT_SIZE];
+        ThreadRng::default().fill_bytes(&mut nonce);
+
+        let mut pad = Zeroizing::new(vec![0; root.len()]);
+        kdf::derive(&self.volume_key, &nonce, &ROOT_KDF_PARAMS, &mut pad)?;
+
+        let mut wrapped_root = root.to_vec();
+        kdf::xor(&mut wrapped_root, &pad);
+
+        self.header.nonce = nonce;
+        self.header.wrapped_root = wrapped_root;
+
+        let blob = bincode::serialize(&self.header)?;
+        self.inner.seal_root(&blob).map_err(|_| Error::Enclave)
+    }
+
+    fn unseal_root(&mut self) -> Result<Vec<u8>, Self::Error> {
+        let blob = self.inner.unseal_root().map_err(|_| Error::Enclave)?;
+        self.unwrap_root(&blob)
+    }
+
+    fn unseal_roots(&mut self) -> Result<Vec<Vec<u8>>, Self::Error> {
+        self.inner
+            .unseal_roots()
+            .map_err(|_| Error::Enclave)?
+            .iter()
+            .map(|blob| self.unwrap_root(blob))
+            .collect()
+    }
+
+    fn retire_root(&mut self) -> Result<(), Self::Error> {
+        self.inner.retire_root().map_err(|_| Error::Enclave)
+    }
+
+    // The header is reloaded from the root that's now the newest, so the discarded root isn't
+    // resealed along with the header the next time the slots change.
+    fn discard_root(&mut self) -> Result<(), Self::Error> {
+        self.inner.discard_root().map_err(|_| Error::Enclave)?;
+        if let Ok(blob) = self.inner.unseal_root() {
+            self.header = bincode::deserialize(&blob)?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Self::Error> {
+        self.inner.sync().map_err(|_| Error::Enclave)
+    }
+
+    fn counter(&mut self) -> Result<u64, Self::Error> {
+        self.inner.counter().map_err(|_| Error::Enclave)
+    }
+
+    fn increment_counter(&mut self) -> Result<u64, Self::Error> {
+        self.inner.increment_counter().map_err(|_| Error::Enclave)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enclave::FileEnclave;
+    use anyhow::Result;
+    use embedded_io::adapters::FromStd;
+    use tempfile::NamedTempFile;
+
+    // Cheap parameters so the tests run quickly.
+    const PARAMS: KdfParams = KdfParams {
+        m_cost: 8,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn multiple_credentials() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        let mut enclave = KeySlotEnclave::create(
+            FileEnclave::new(FromStd::new(file.reopen()?)),
+            "admin",
+            b"hunter2",
+            PARAMS,
+        )?;
+        enclave.seal_root(&[7; 32])?;
+        enclave.retire_root()?;
+        enclave.add_slot("recovery", b"correct horse")?;
+
+        for credential in [&b"hunter2"[..], &b"correct horse"[..]] {
+            let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+            let mut enclave = KeySlotEnclave::unlock(inner, credential, PARAMS)?;
+            assert_eq!(enclave.unseal_root()?, vec![7; 32]);
+        }
+
+        let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        assert!(matches!(
+            KeySlotEnclave::unlock(inner, b"hunter3", PARAMS),
+            Err(Error::NoKeySlot)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn revoke() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        let mut enclave = KeySlotEnclave::create(
+            FileEnclave::new(FromStd::new(file.reopen()?)),
+            "admin",
+            b"hunter2",
+            PARAMS,
+        )?;
+        enclave.seal_root(&[7; 32])?;
+        enclave.retire_root()?;
+
+        let recovery = enclave.add_slot("recovery", b"correct horse")?;
+        enclave.revoke_slot(0)?;
+        assert_eq!(
+            enclave.slots(),
+            vec![KeySlotInfo {
+                id: recovery,
+                label: "recovery".to_string(),
+            }]
+        );
+        assert!(matches!(
+            enclave.revoke_slot(recovery),
+            Err(Error::LastKeySlot)
+        ));
+
+        // The revoked credential no longer unlocks anything, and its slot is gone for good.
+        let mut inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        assert_eq!(inner.unseal_roots()?.len(), 1);
+        assert!(KeySlotEnclave::unlock(inner, b"hunter2", PARAMS).is_err());
+
+        let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        let mut enclave = KeySlotEnclave::unlock(inner, b"correct horse", PARAMS)?;
+        assert_eq!(enclave.unseal_root()?, vec![7; 32]);
+
+        Ok(())
+    }
+
+    // Discarding a stale root, as `Lethe::load_state()` does, doesn't bring it back when the
+    // slots change.
+    #[test]
+    fn discard_then_add_slot() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        let mut enclave = KeySlotEnclave::create(
+            FileEnclave::new(FromStd::new(file.reopen()?)),
+            "admin",
+            b"hunter2",
+            PARAMS,
+        )?;
+        enclave.seal_root(&[1; 32])?;
+        enclave.retire_root()?;
+        enclave.seal_root(&[2; 32])?;
+
+        let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        let mut enclave = KeySlotEnclave::unlock(inner, b"hunter2", PARAMS)?;
+        enclave.discard_root()?;
+        enclave.add_slot("recovery", b"correct horse")?;
+        assert_eq!(enclave.unseal_roots()?, vec![vec![1; 32]]);
+
+        let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        let mut enclave = KeySlotEnclave::unlock(inner, b"correct horse", PARAMS)?;
+        assert_eq!(enclave.unseal_root()?, vec![1; 32]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+mod file;
+mod kdf;
+mod keyslot;
+mod passphrase;
+mod shamir;
+mod sim;
+mod threshold;
+
+pub use file::{FileEnclave, SyncIo};
+pub use kdf::KdfParams;
+pub use keyslot::{KeySlotEnclave, KeySlotInfo};
+pub use passphrase::PassphraseEnclave;
+pub use sim::SimEnclave;
+pub use threshold::{ShareStatus, ThresholdEnclave};
+
+/// Secure storage for the root of trust of a `Lethe` instance.
+///
+/// The root (the master key) is all that's needed to decrypt the rest of a `Lethe` instance's
+/// state, so an `Enclave` must be able to overwrite it in a way that makes the previous root
+/// unrecoverable. The monotonic counter lets `Lethe` detect its untrusted storage being rolled
+/// back to an earlier state.
+///
+/// An `Enclave` may hold on to previously sealed roots until they're retired. This lets `Lethe`
+/// recover if it crashes after sealing a new root but before committing the state it protects.
+pub trait Enclave {
+    type Error;
+
+    /// Returns the maximum number of bytes that can be sealed.
+    fn capacity(&self) -> usize;
+
+    /// Seals `root`. Enclaves that can hold several roots must not overwrite the newest one.
+    fn seal_root(&mut self, root: &[u8]) -> Result<(), Self::Error>;
+
+    /// Unseals the most recently sealed root.
+    fn unseal_root(&mut self) -> Result<Vec<u8>, Self::Error>;
+
+    /// Unseals every held root, newest first.
+    fn unseal_roots(&mut self) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(vec![self.unseal_root()?])
+    }
+
+    /// Securely erases every root but the most recently sealed one.
+    fn retire_root(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Securely erases the most recently sealed root, making the one before it the newest.
+    fn discard_root(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Makes everything sealed so far durable. Enclaves that write through to durable storage
+    /// can rely on the default, which does nothing.
+    fn sync(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Returns the current value of the monotonic counter.
+    fn counter(&mut self) -> Result<u64, Self::Error>;
+
+    /// Increments the monotonic counter, returning its new value.
+    fn increment_counter(&mut self) -> Result<u64, Self::Error>;
+}
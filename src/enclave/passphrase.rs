@@ -0,0 +1,281 @@
+use super::{
+    kdf::{self, KdfParams, ROOT_KDF_PARAMS, SALT_SIZE, VERIFIER_SIZE},
+    Enclave,
+};
+use crate::error::Error;
+use rand::{rngs::ThreadRng, RngCore};
+use zeroize::Zeroizing;
+
+// Size of the key that roots are wrapped under.
+const KEK_SIZE: usize = 32;
+
+// Size of what's sealed ahead of each wrapped root: the salt, the verifier, and a nonce.
+const OVERHEAD: usize = SALT_SIZE + VERIFIER_SIZE + SALT_SIZE;
+
+/// An `Enclave` that wraps roots under a passphrase (or the contents of a keyfile) before
+/// sealing them in an inner `Enclave`. This is meant for hosts without secure storage, where the
+/// inner enclave is just a plain file.
+///
+/// A wrapping key is derived from the passphrase and a random salt once, using a memory-hard
+/// KDF, and the passphrase itself isn't kept. Since the wrapping key is uniformly random, each
+/// root is then cheaply wrapped under it with a fresh nonce, like `KeySlotEnclave` does. The
+/// sealed blob is laid out as the salt, a verifier used to detect wrong passphrases, the nonce,
+/// then the wrapped root.
+pub struct PassphraseEnclave<S> {
+    inner: S,
+    salt: [u8; SALT_SIZE],
+    verifier: [u8; VERIFIER_SIZE],
+    kek: Zeroizing<Vec<u8>>,
+    params: KdfParams,
+}
+
+impl<S> PassphraseEnclave<S>
+where
+    S: Enclave,
+{
+    /// Wraps a fresh `inner` enclave. Use `unlock()` for an enclave that already holds a root.
+    pub fn new(inner: S, passphrase: &[u8]) -> Result<Self, Error> {
+        Self::with_params(inner, passphrase, KdfParams::default())
+    }
+
+    pub fn with_params(inner: S, passphrase: &[u8], params: KdfParams) -> Result<Self, Error> {
+        let mut salt = [0; SALT_SIZE];
+        ThreadRng::default().fill_bytes(&mut salt);
+        let (verifier, kek) = derive_kek(passphrase, &salt, &params)?;
+
+        Ok(Self {
+            inner,
+            salt,
+            verifier,
+            kek,
+            params,
+        })
+    }
+
+    /// Wraps an `inner` enclave that already holds a root, failing cleanly with
+    /// `Error::WrongPassphrase` if it wasn't sealed under `passphrase`.
+    pub fn unlock(mut inner: S, passphrase: &[u8], params: KdfParams) -> Result<Self, Error> {
+        let blob = inner.unseal_root().map_err(|_| Error::Enclave)?;
+        if blob.len() < OVERHEAD {
+            return Err(Error::Enclave);
+        }
+
+        let salt: [u8; SALT_SIZE] = blob[..SALT_SIZE].try_into().unwrap();
+        let (verifier, kek) = derive_kek(passphrase, &salt, &params)?;
+        if !kdf::ct_eq(&verifier, &blob[SALT_SIZE..SALT_SIZE + VERIFIER_SIZE]) {
+            return Err(Error::WrongPassphrase);
+        }
+
+        Ok(Self {
+            inner,
+            salt,
+            verifier,
+            kek,
+            params,
+        })
+    }
+
+    /// Changes the passphrase without changing the root itself, so the store doesn't need to be
+    /// re-keyed. Only the newest root is re-wrapped; the blob wrapped under the old passphrase is
+    /// then securely erased.
+    pub fn change_passphrase(&mut self, passphrase: &[u8]) -> Result<(), Error> {
+        let root = Zeroizing::new(self.unseal_root()?);
+
+        let mut salt = [0; SALT_SIZE];
+        ThreadRng::default().fill_bytes(&mut salt);
+        let (verifier, kek) = derive_kek(passphrase, &salt, &self.params)?;
+        (self.salt, self.verifier, self.kek) = (salt, verifier, kek);
+
+        self.seal_root(&root)?;
+        self.retire_root()
+    }
+
+    /// Consumes the enclave, returning the inner `Enclave`.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn wrap_root(&self, root: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut nonce = [0; SALT_SIZE];
+        ThreadRng::default().fill_bytes(&mut nonce);
+
+        let mut pad = Zeroizing::new(vec![0; root.len()]);
+        kdf::derive(&self.kek, &nonce, &ROOT_KDF_PARAMS, &mut pad)?;
+
+        let mut blob = Vec::with_capacity(OVERHEAD + root.len());
+        blob.extend_from_slice(&self.salt);
+        blob.extend_from_slice(&self.verifier);
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(root);
+        kdf::xor(&mut blob[OVERHEAD..], &pad);
+
+        Ok(blob)
+    }
+
+    fn unwrap_root(&self, blob: &[u8]) -> Result<Vec<u8>, Error> {
+        if blob.len() < OVERHEAD {
+            return Err(Error::Enclave);
+        }
+
+        let (salt, rest) = blob.split_at(SALT_SIZE);
+        let (verifier, rest) = rest.split_at(VERIFIER_SIZE);
+        let (nonce, wrapped) = rest.split_at(SALT_SIZE);
+
+        // Blobs wrapped under another passphrase have a different salt or verifier.
+        if salt != self.salt || !kdf::ct_eq(verifier, &self.verifier) {
+            return Err(Error::WrongPassphrase);
+        }
+
+        let mut pad = Zeroizing::new(vec![0; wrapped.len()]);
+        kdf::derive(&self.kek, nonce, &ROOT_KDF_PARAMS, &mut pad)?;
+
+        let mut root = wrapped.to_vec();
+        kdf::xor(&mut root, &pad);
+        Ok(root)
+    }
+}
+
+// Derives the verifier and the wrapping key for `passphrase` from `salt`.
+fn derive_kek(
+    passphrase: &[u8],
+    salt: &[u8],
+    params: &KdfParams,
+) -> Result<([u8; VERIFIER_SIZE], Zeroizing<Vec<u8>>), Error> {
+    let mut derived = Zeroizing::new(vec![0; VERIFIER_SIZE + KEK_SIZE]);
+    kdf::derive(passphrase, salt, params, &mut derived)?;
+    let (verifier, kek) = derived.split_at(VERIFIER_SIZE);
+
+    Ok((verifier.try_into().unwrap(), Zeroizing::new(kek.to_vec())))
+}
+
+impl<S> Enclave for PassphraseEnclave<S>
+where
+    S: Enclave,
+{
+    type Error = Error;
+
+    fn capacity(&self) -> usize {
+        self.inner.capacity().saturating_sub(OVERHEAD)
+    }
+
+    fn seal_root(&mut self, root: &[u8]) -> Result<(), Self::Error> {
+        if root.len() > self.capacity() {
+            return Err(Error::EnclaveCapacity);
+        }
+
+        let blob = self.wrap_root(root)?;
+        self.inner.seal_root(&blob).map_err(|_| Error::Enclave)
+    }
+
+    fn unseal_root(&mut self) -> Result<Vec<u8>, Self::Error> {
+        let blob = self.inner.unseal_root().map_err(|_| Error::Enclave)?;
+        self.unwrap_root(&blob)
+    }
+
+    fn unseal_roots(&mut self) -> Result<Vec<Vec<u8>>, Self::Error> {
+        self.inner
+            .unseal_roots()
+            .map_err(|_| Error::Enclave)?
+            .iter()
+            .map(|blob| self.unwrap_root(blob))
+            .collect()
+    }
+
+    fn retire_root(&mut self) -> Result<(), Self::Error> {
+        self.inner.retire_root().map_err(|_| Error::Enclave)
+    }
+
+    fn discard_root(&mut self) -> Result<(), Self::Error> {
+        self.inner.discard_root().map_err(|_| Error::Enclave)
+    }
+
+    fn sync(&mut self) -> Result<(), Self::Error> {
+        self.inner.sync().map_err(|_| Error::Enclave)
+    }
+
+    fn counter(&mut self) -> Result<u64, Self::Error> {
+        self.inner.counter().map_err(|_| Error::Enclave)
+    }
+
+    fn increment_counter(&mut self) -> Result<u64, Self::Error> {
+        self.inner.increment_counter().map_err(|_| Error::Enclave)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enclave::FileEnclave;
+    use anyhow::Result;
+    use embedded_io::adapters::FromStd;
+    use tempfile::NamedTempFile;
+
+    // Cheap parameters so the tests run quickly.
+    const PARAMS: KdfParams = KdfParams {
+        m_cost: 8,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn seal_unseal() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        let mut enclave = PassphraseEnclave::with_params(
+            FileEnclave::new(FromStd::new(file.reopen()?)),
+            b"hunter2",
+            PARAMS,
+        )?;
+        enclave.seal_root(&[7; 32])?;
+
+        // The root isn't stored in the clear.
+        let mut raw = FileEnclave::new(FromStd::new(file.reopen()?));
+        let blob = raw.unseal_root()?;
+        assert_ne!(&blob[OVERHEAD..], &[7; 32]);
+
+        let mut enclave = PassphraseEnclave::unlock(raw, b"hunter2", PARAMS)?;
+        assert_eq!(enclave.unseal_root()?, vec![7; 32]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_passphrase() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        let mut enclave = PassphraseEnclave::with_params(
+            FileEnclave::new(FromStd::new(file.reopen()?)),
+            b"hunter2",
+            PARAMS,
+        )?;
+        enclave.seal_root(&[7; 32])?;
+
+        let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        assert!(matches!(
+            PassphraseEnclave::unlock(inner, b"hunter3", PARAMS),
+            Err(Error::WrongPassphrase)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn change_passphrase() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        let mut enclave = PassphraseEnclave::with_params(
+            FileEnclave::new(FromStd::new(file.reopen()?)),
+            b"hunter2",
+            PARAMS,
+        )?;
+        enclave.seal_root(&[7; 32])?;
+        enclave.retire_root()?;
+        enclave.change_passphrase(b"correct horse")?;
+
+        let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        assert!(PassphraseEnclave::unlock(inner, b"hunter2", PARAMS).is_err());
+
+        let inner = FileEnclave::new(FromStd::new(file.reopen()?));
+        let mut enclave = PassphraseEnclave::unlock(inner, b"correct horse", PARAMS)?;
+        assert_eq!(enclave.unseal_root()?, vec![7; 32]);
+
+        Ok(())
+    }
+}
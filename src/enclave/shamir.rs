@@ -0,0 +1,111 @@
+//! Shamir secret sharing over GF(2^8), applied bytewise.
+
+use rand::RngCore;
+use zeroize::Zeroize;
+
+// Multiplication in GF(2^8) modulo the AES polynomial.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// Multiplicative inverse in GF(2^8), i.e. `a^254`.
+fn inv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = mul(result, a);
+    }
+    result
+}
+
+/// Splits `secret` into `n` shares, any `k` of which can reconstruct it. Shares are returned as
+/// their x-coordinate (in `1..=n`) and the share bytes.
+pub(crate) fn split(secret: &[u8], n: u8, k: u8, rng: &mut impl RngCore) -> Vec<(u8, Vec<u8>)> {
+    let mut shares: Vec<(u8, Vec<u8>)> = (1..=n)
+        .map(|x| (x, Vec::with_capacity(secret.len())))
+        .collect();
+    let mut coeffs = vec![0; k as usize];
+
+    for byte in secret {
+        // A fresh random polynomial of degree `k - 1` whose constant term is the secret byte.
+        coeffs[0] = *byte;
+        rng.fill_bytes(&mut coeffs[1..]);
+
+        for (x, share) in shares.iter_mut() {
+            let y = coeffs.iter().rev().fold(0, |acc, c| mul(acc, *x) ^ c);
+            share.push(y);
+        }
+    }
+
+    coeffs.zeroize();
+    shares
+}
+
+/// Reconstructs a secret from shares with distinct x-coordinates. Given fewer shares than the
+/// threshold the secret was split with, the result is garbage.
+pub(crate) fn combine(shares: &[(u8, &[u8])]) -> Vec<u8> {
+    let len = shares.first().map_or(0, |(_, y)| y.len());
+    let mut secret = vec![0; len];
+
+    for (i, (xi, yi)) in shares.iter().enumerate() {
+        // The Lagrange basis polynomial for `xi`, evaluated at 0.
+        let basis = shares
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(1, |acc, (_, (xj, _))| mul(acc, mul(*xj, inv(xj ^ xi))));
+
+        for (s, y) in secret.iter_mut().zip(yi.iter()) {
+            *s ^= mul(*y, basis);
+        }
+    }
+
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::ThreadRng;
+
+    #[test]
+    fn field() {
+        for a in 1..=255 {
+            assert_eq!(mul(a, inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn any_k_shares() {
+        let secret = [0xa5; 32];
+        let shares = split(&secret, 5, 3, &mut ThreadRng::default());
+
+        for (a, b, c) in [(0, 1, 2), (0, 2, 4), (1, 3, 4), (4, 2, 0)] {
+            let subset: Vec<_> = [a, b, c]
+                .iter()
+                .map(|&i| (shares[i].0, &shares[i].1[..]))
+                .collect();
+            assert_eq!(combine(&subset), secret);
+        }
+    }
+
+    #[test]
+    fn too_few_shares() {
+        let secret = [0xa5; 32];
+        let shares = split(&secret, 5, 3, &mut ThreadRng::default());
+
+        let subset: Vec<_> = shares[..2].iter().map(|(x, y)| (*x, &y[..])).collect();
+        assert_ne!(combine(&subset), secret);
+    }
+}
@@ -0,0 +1,143 @@
+use super::Enclave;
+use crate::error::Error;
+use rand::{rngs::ThreadRng, RngCore};
+use std::{cell::RefCell, rc::Rc};
+
+// Default number of bytes of non-volatile storage.
+const DEFAULT_CAPACITY: usize = 256;
+
+struct Tpm {
+    // Secret that never leaves the simulated device. Sealed roots are bound to it.
+    platform_secret: Vec<u8>,
+    nv: Option<Vec<u8>>,
+    counter: u64,
+    capacity: usize,
+}
+
+/// A simulated TPM-like `Enclave` for testing.
+///
+/// Roots are sealed to a secret that's unique to the simulated device, non-volatile storage is
+/// limited, and the counter can only ever go up. Clones share the same device, so a test can
+/// hold on to one while a `Lethe` instance owns another, then reopen the store with it.
+#[derive(Clone)]
+pub struct SimEnclave {
+    tpm: Rc<RefCell<Tpm>>,
+}
+
+impl SimEnclave {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut platform_secret = vec![0; capacity];
+        ThreadRng::default().fill_bytes(&mut platform_secret);
+
+        Self {
+            tpm: Rc::new(RefCell::new(Tpm {
+                platform_secret,
+                nv: None,
+                counter: 0,
+                capacity,
+            })),
+        }
+    }
+
+    /// Returns the raw contents of the device's non-volatile storage, as an attacker with
+    /// physical access to it would see them.
+    pub fn raw(&self) -> Option<Vec<u8>> {
+        self.tpm.borrow().nv.clone()
+    }
+
+    /// Overwrites the raw contents of the device's non-volatile storage, as an attacker with
+    /// physical access to it could. The counter is unaffected.
+    pub fn set_raw(&self, nv: Option<Vec<u8>>) {
+        self.tpm.borrow_mut().nv = nv;
+    }
+}
+
+impl Default for SimEnclave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enclave for SimEnclave {
+    type Error = Error;
+
+    fn capacity(&self) -> usize {
+        self.tpm.borrow().capacity
+    }
+
+    fn seal_root(&mut self, root: &[u8]) -> Result<(), Self::Error> {
+        let mut tpm = self.tpm.borrow_mut();
+
+        if root.len() > tpm.capacity {
+            return Err(Error::EnclaveCapacity);
+        }
+
+        let sealed = root
+            .iter()
+            .zip(&tpm.platform_secret)
+            .map(|(a, b)| a ^ b)
+            .collect();
+        tpm.nv = Some(sealed);
+
+        Ok(())
+    }
+
+    fn unseal_root(&mut self) -> Result<Vec<u8>, Self::Error> {
+        let tpm = self.tpm.borrow();
+        let sealed = tpm.nv.as_ref().ok_or(Error::Enclave)?;
+
+        Ok(sealed
+            .iter()
+            .zip(&tpm.platform_secret)
+            .map(|(a, b)| a ^ b)
+            .collect())
+    }
+
+    fn counter(&mut self) -> Result<u64, Self::Error> {
+        Ok(self.tpm.borrow().counter)
+    }
+
+    fn increment_counter(&mut self) -> Result<u64, Self::Error> {
+        let mut tpm = self.tpm.borrow_mut();
+        tpm.counter = tpm.counter.checked_add(1).ok_or(Error::Enclave)?;
+        Ok(tpm.counter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn seal_unseal() -> Result<()> {
+        let mut enclave = SimEnclave::new();
+        enclave.seal_root(&[7; 32])?;
+
+        // The sealed root isn't stored in the clear.
+        assert_ne!(enclave.raw().unwrap(), vec![7; 32]);
+
+        // Clones share the same device.
+        assert_eq!(enclave.clone().unseal_root()?, vec![7; 32]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unseal_empty() {
+        assert!(SimEnclave::new().unseal_root().is_err());
+    }
+
+    #[test]
+    fn capacity() {
+        let mut enclave = SimEnclave::with_capacity(16);
+        assert!(matches!(
+            enclave.seal_root(&[0; 32]),
+            Err(Error::EnclaveCapacity)
+        ));
+    }
+}
@@ -0,0 +1,428 @@
+use super::{
+    kdf::{self, ROOT_KDF_PARAMS},
+    shamir, Enclave,
+};
+use crate::error::Error;
+use rand::rngs::ThreadRng;
+use std::collections::BTreeSet;
+use zeroize::{Zeroize, Zeroizing};
+
+// Each share is sealed as its sequence number and x-coordinate, followed by the share bytes.
+const SHARE_HEADER_SIZE: usize = 9;
+
+// Size of the tag split along with each root, used to check that a combined root is intact.
+const TAG_SIZE: usize = 32;
+
+// Salt for deriving tags. Roots are uniformly random, so a fixed salt is fine.
+const TAG_SALT: &[u8] = b"lethe-threshold-tag";
+
+/// The state of an enclave's share, as reported by `ThresholdEnclave::status()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareStatus {
+    /// The enclave holds a share of the newest root.
+    Current,
+    /// The enclave only holds shares of older roots.
+    Stale,
+    /// The enclave couldn't be reached or holds no valid shares.
+    Missing,
+}
+
+struct Share {
+    seq: u64,
+    x: u8,
+    y: Vec<u8>,
+}
+
+impl Share {
+    fn encode(&self) -> Vec<u8> {
+        let mut blob = Vec::with_capacity(SHARE_HEADER_SIZE + self.y.len());
+        blob.extend_from_slice(&self.seq.to_le_bytes());
+        blob.push(self.x);
+        blob.extend_from_slice(&self.y);
+        blob
+    }
+
+    fn decode(blob: &[u8]) -> Option<Self> {
+        if blob.len() < SHARE_HEADER_SIZE || blob[8] == 0 {
+            return None;
+        }
+
+        Some(Self {
+            seq: u64::from_le_bytes(blob[..8].try_into().unwrap()),
+            x: blob[8],
+            y: blob[SHARE_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.y.zeroize();
+    }
+}
+
+// Derives the tag that's split along with `root`.
+fn tag(root: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut tag = Zeroizing::new(vec![0; TAG_SIZE]);
+    kdf::derive(root, TAG_SALT, &ROOT_KDF_PARAMS, &mut tag)?;
+    Ok(tag)
+}
+
+// Combines the first `k` of `shares` whose combination carries a valid tag, trying each
+// `k`-subset in turn so that a corrupted or forged share doesn't keep the root from being
+// recovered. Returns `None` if no subset checks out.
+fn combine_checked(shares: &[(u8, &[u8])], k: usize) -> Option<Vec<u8>> {
+    let mut subset: Vec<usize> = (0..k).collect();
+
+    loop {
+        let picked: Vec<(u8, &[u8])> = subset.iter().map(|&i| shares[i]).collect();
+        let mut combined = Zeroizing::new(shamir::combine(&picked));
+
+        if combined.len() >= TAG_SIZE {
+            let (root, expected) = combined.split_at(combined.len() - TAG_SIZE);
+            if tag(root).is_ok_and(|tag| kdf::ct_eq(&tag, expected)) {
+                combined.truncate(root.len());
+                return Some(combined.to_vec());
+            }
+        }
+
+        // Advance to the next subset in lexicographic order.
+        let i = (0..k).rev().find(|&i| subset[i] < shares.len() - k + i)?;
+        subset[i] += 1;
+        for j in i + 1..k {
+            subset[j] = subset[j - 1] + 1;
+        }
+    }
+}
+
+/// An `Enclave` that splits roots across several enclaves using Shamir secret sharing, so that
+/// any `threshold` of them can reconstruct a root but fewer learn nothing about it.
+///
+/// Every seal writes fresh shares to every enclave. Enclaves that can't be written to, or that
+/// are left holding shares of an older root, are reported by `status()`. Each root is split along
+/// with a tag, so a root combined from corrupted shares is detected rather than returned, and the
+/// other combinations of shares are tried instead.
+pub struct ThresholdEnclave<S> {
+    enclaves: Vec<S>,
+    threshold: usize,
+    status: Vec<ShareStatus>,
+}
+
+impl<S> ThresholdEnclave<S>
+where
+    S: Enclave,
+{
+    pub fn new(enclaves: Vec<S>, threshold: usize) -> Result<Self, Error> {
+        if threshold == 0 || threshold > enclaves.len() || enclaves.len() > u8::MAX as usize {
+            return Err(Error::InvalidThreshold);
+        }
+
+        Ok(Self {
+            status: vec![ShareStatus::Missing; enclaves.len()],
+            enclaves,
+            threshold,
+        })
+    }
+
+    /// Returns the state of each enclave's share as of the last seal or unseal.
+    pub fn status(&self) -> &[ShareStatus] {
+        &self.status
+    }
+
+    /// Returns the indices of the enclaves that are missing or stale.
+    pub fn degraded(&self) -> Vec<usize> {
+        self.status
+            .iter()
+            .enumerate()
+            .filter(|(_, status)| **status != ShareStatus::Current)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Consumes the enclave, returning the inner `Enclave`s.
+    pub fn into_inner(self) -> Vec<S> {
+        self.enclaves
+    }
+
+    // Collects the shares held by each enclave, or `None` for enclaves that can't be reached.
+    fn shares(&mut self) -> Vec<Option<Vec<Share>>> {
+        self.enclaves
+            .iter_mut()
+            .map(|enclave| {
+                enclave.unseal_roots().ok().map(|blobs| {
+                    blobs
+                        .iter()
+                        .filter_map(|blob| Share::decode(blob))
+                        .collect()
+                })
+            })
+            .collect()
+    }
+}
+
+impl<S> Enclave for ThresholdEnclave<S>
+where
+    S: Enclave,
+{
+    type Error = Error;
+
+    fn capacity(&self) -> usize {
+        self.enclaves
+            .iter()
+            .map(|enclave| {
+                enclave
+                    .capacity()
+                    .saturating_sub(SHARE_HEADER_SIZE + TAG_SIZE)
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn seal_root(&mut self, root: &[u8]) -> Result<(), Self::Error> {
+        if root.len() > self.capacity() {
+            return Err(Error::EnclaveCapacity);
+        }
+
+        let seq = self
+            .shares()
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|share| share.seq)
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        let mut secret = Zeroizing::new(root.to_vec());
+        secret.extend_from_slice(&tag(root)?);
+
+        let shares = shamir::split(
+            &secret,
+            self.enclaves.len() as u8,
+            self.threshold as u8,
+            &mut ThreadRng::default(),
+        );
+
+        let mut written = 0;
+        for (index, (x, y)) in shares.into_iter().enumerate() {
+            let blob = Zeroizing::new(Share { seq, x, y }.encode());
+            self.status[index] = match self.enclaves[index].seal_root(&blob) {
+                Ok(()) => {
+                    written += 1;
+                    ShareStatus::Current
+                }
+                Err(_) => ShareStatus::Missing,
+            };
+        }
+
+        if written < self.threshold {
+            return Err(Error::NotEnoughShares);
+        }
+
+        Ok(())
+    }
+
+    fn unseal_root(&mut self) -> Result<Vec<u8>, Self::Error> {
+        self.unseal_roots()?
+            .into_iter()
+            .next()
+            .ok_or(Error::NotEnoughShares)
+    }
+
+    fn unseal_roots(&mut self) -> Result<Vec<Vec<u8>>, Self::Error> {
+        let shares = self.shares();
+        let seqs: BTreeSet<u64> = shares
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|share| share.seq)
+            .collect();
+
+        let mut roots = vec![];
+        let mut newest = None;
+
+        // Seqs whose shares don't combine into an intact root are skipped, as if too few shares
+        // were left.
+        for seq in seqs.into_iter().rev() {
+            let matching: Vec<(u8, &[u8])> = shares
+                .iter()
+                .flatten()
+                .filter_map(|held| held.iter().find(|share| share.seq == seq))
+                .map(|share| (share.x, &share.y[..]))
+                .collect();
+
+            if matching.len() < self.threshold {
+                continue;
+            }
+
+            if let Some(root) = combine_checked(&matching, self.threshold) {
+                roots.push(root);
+                newest.get_or_insert(seq);
+            }
+        }
+
+        let newest = newest.ok_or(Error::NotEnoughShares)?;
+        self.status = shares
+            .iter()
+            .map(|held| match held {
+                Some(held) if held.iter().any(|share| share.seq == newest) => ShareStatus::Current,
+                Some(held) if !held.is_empty() => ShareStatus::Stale,
+                _ => ShareStatus::Missing,
+            })
+            .collect();
+
+        Ok(roots)
+    }
+
+    // Unreachable enclaves are skipped; they'll be brought up to date by the next seal.
+    fn retire_root(&mut self) -> Result<(), Self::Error> {
+        for (index, enclave) in self.enclaves.iter_mut().enumerate() {
+            if enclave.retire_root().is_err() {
+                self.status[index] = ShareStatus::Missing;
+            }
+        }
+        Ok(())
+    }
+
+    // Only the enclaves that actually received the newest shares discard them. The others still
+    // hold the previous shares as their newest, which must be kept.
+    fn discard_root(&mut self) -> Result<(), Self::Error> {
+        let shares = self.shares();
+        let newest = match shares
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|share| share.seq)
+            .max()
+        {
+            Some(newest) => newest,
+            None => return Ok(()),
+        };
+
+        for (enclave, held) in self.enclaves.iter_mut().zip(&shares) {
+            let holds_newest = held
+                .as_ref()
+                .and_then(|held| held.iter().map(|share| share.seq).max())
+                == Some(newest);
+
+            if holds_newest {
+                enclave.discard_root().map_err(|_| Error::Enclave)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Like retiring, unreachable enclaves are skipped.
+    fn sync(&mut self) -> Result<(), Self::Error> {
+        for (index, enclave) in self.enclaves.iter_mut().enumerate() {
+            if enclave.sync().is_err() {
+                self.status[index] = ShareStatus::Missing;
+            }
+        }
+        Ok(())
+    }
+
+    fn counter(&mut self) -> Result<u64, Self::Error> {
+        self.enclaves
+            .iter_mut()
+            .filter_map(|enclave| enclave.counter().ok())
+            .max()
+            .ok_or(Error::Enclave)
+    }
+
+    // Lagging counters are caught up, so every reachable enclave ends up with the same value.
+    fn increment_counter(&mut self) -> Result<u64, Self::Error> {
+        let counter = self.counter()? + 1;
+
+        for enclave in self.enclaves.iter_mut() {
+            while let Ok(current) = enclave.counter() {
+                if current >= counter || enclave.increment_counter().is_err() {
+                    break;
+                }
+            }
+        }
+
+        Ok(counter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enclave::SimEnclave;
+    use anyhow::Result;
+
+    #[test]
+    fn any_k_enclaves() -> Result<()> {
+        let devices = vec![SimEnclave::new(), SimEnclave::new(), SimEnclave::new()];
+        let mut enclave = ThresholdEnclave::new(devices.clone(), 2)?;
+        enclave.seal_root(&[7; 32])?;
+
+        // No single device holds the root.
+        for device in &devices {
+            let mut share = device.clone().unseal_root()?;
+            share.drain(..SHARE_HEADER_SIZE);
+            assert_ne!(share, vec![7; 32]);
+        }
+
+        // Losing one device is fine.
+        devices[1].set_raw(None);
+        assert_eq!(enclave.unseal_root()?, vec![7; 32]);
+        assert_eq!(
+            enclave.status(),
+            &[
+                ShareStatus::Current,
+                ShareStatus::Missing,
+                ShareStatus::Current
+            ]
+        );
+
+        // Losing two isn't.
+        devices[2].set_raw(None);
+        assert!(enclave.unseal_root().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn stale_share() -> Result<()> {
+        let devices = vec![SimEnclave::new(), SimEnclave::new(), SimEnclave::new()];
+        let mut enclave = ThresholdEnclave::new(devices.clone(), 2)?;
+
+        enclave.seal_root(&[1; 32])?;
+        let old = devices[2].raw();
+        enclave.seal_root(&[2; 32])?;
+        devices[2].set_raw(old);
+
+        assert_eq!(enclave.unseal_root()?, vec![2; 32]);
+        assert_eq!(enclave.degraded(), vec![2]);
+
+        Ok(())
+    }
+
+    // A corrupted share is outvoted by the others.
+    #[test]
+    fn corrupted_share() -> Result<()> {
+        let devices = vec![SimEnclave::new(), SimEnclave::new(), SimEnclave::new()];
+        let mut enclave = ThresholdEnclave::new(devices.clone(), 2)?;
+        enclave.seal_root(&[7; 32])?;
+
+        let mut blob = devices[0].clone().unseal_root()?;
+        blob[SHARE_HEADER_SIZE] ^= 1;
+        devices[0].clone().seal_root(&blob)?;
+        assert_eq!(enclave.unseal_root()?, vec![7; 32]);
+
+        // With only the corrupted share and one other, there's nothing to fall back on.
+        devices[2].set_raw(None);
+        assert!(enclave.unseal_root().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_threshold() {
+        assert!(ThresholdEnclave::new(vec![SimEnclave::new()], 2).is_err());
+        assert!(ThresholdEnclave::new(vec![SimEnclave::new()], 0).is_err());
+    }
+}
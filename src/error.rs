@@ -1,3 +1,4 @@
+use std::convert::Infallible;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -20,6 +21,66 @@ pub enum Error {
     #[error(transparent)]
     Khf(#[from] khf::Error),
 
+    #[error("invalid khf fanouts")]
+    InvalidFanouts,
+
+    #[error("enclave error")]
+    Enclave,
+
+    #[error("exceeded enclave capacity")]
+    EnclaveCapacity,
+
+    #[error("storage was rolled back")]
+    Rollback,
+
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+
+    #[error("key derivation failed")]
+    Kdf,
+
+    #[error("no matching key slot")]
+    NoKeySlot,
+
+    #[error("can't revoke the last key slot")]
+    LastKeySlot,
+
+    #[error("invalid share threshold")]
+    InvalidThreshold,
+
+    #[error("not enough enclave shares")]
+    NotEnoughShares,
+
+    #[error("no such snapshot")]
+    MissingSnapshot,
+
+    #[error("object already exists")]
+    ObjectExists,
+
+    #[error("failed to lock memory")]
+    MemoryLock,
+
+    #[error("no such name")]
+    MissingName,
+
+    #[error("not a directory")]
+    NotADirectory,
+
+    #[error("directory not empty")]
+    DirectoryNotEmpty,
+
+    #[error("invalid path")]
+    InvalidPath,
+
+    #[error("object isn't in the trash")]
+    MissingTrash,
+
     #[error("unknown error")]
     Unknown,
 }
+
+impl From<Infallible> for Error {
+    fn from(err: Infallible) -> Self {
+        match err {}
+    }
+}
@@ -5,6 +5,7 @@ use embedded_io::{
     Io, SeekFrom,
 };
 use std::marker::PhantomData;
+use zeroize::Zeroize;
 
 pub struct CryptIo<IO, C, const KEY_SZ: usize> {
     key: Key<KEY_SZ>,
@@ -22,6 +23,12 @@ impl<IO, C, const KEY_SZ: usize> CryptIo<IO, C, KEY_SZ> {
     }
 }
 
+impl<IO, C, const KEY_SZ: usize> Drop for CryptIo<IO, C, KEY_SZ> {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
 impl<IO, C, const KEY_SZ: usize> Io for CryptIo<IO, C, KEY_SZ>
 where
     IO: Io,
@@ -38,10 +45,11 @@ where
         let mut encrypted = vec![0; buf.len()];
         let n = self.io.read(&mut encrypted)?;
 
-        let decrypted = C::onetime_decrypt(&self.key, &encrypted)
+        let mut decrypted = C::onetime_decrypt(&self.key, &encrypted)
             .map_err(|_| ())
             .unwrap();
         buf.copy_from_slice(&decrypted[..]);
+        decrypted.zeroize();
 
         Ok(n)
     }
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Range};
+
+/// The blocks of an object that read back as zeros, kept as disjoint ranges so that a large hole
+/// takes up as little space as a small one. Adjacent ranges are merged as they're inserted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Holes {
+    // Maps the first block of each range to the block just past it.
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl Holes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if there are no holes.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the whole range of holes that `block` falls in, if it's a hole.
+    pub fn containing(&self, block: u64) -> Option<Range<u64>> {
+        self.ranges
+            .range(..=block)
+            .next_back()
+            .filter(|(_, &end)| end > block)
+            .map(|(&start, &end)| start..end)
+    }
+
+    /// Returns the ranges of holes, in order.
+    pub fn iter(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.ranges.iter().map(|(&start, &end)| start..end)
+    }
+
+    /// Returns the ranges of holes that overlap `blocks`, in order and clipped to `blocks`.
+    pub fn overlapping(&self, blocks: Range<u64>) -> impl Iterator<Item = Range<u64>> + '_ {
+        let first = self
+            .containing(blocks.start)
+            .map_or(blocks.start, |range| range.start);
+
+        self.ranges
+            .range(first..blocks.end.max(first))
+            .map(move |(&start, &end)| start.max(blocks.start)..end.min(blocks.end))
+    }
+
+    /// Makes `blocks` holes, merging them with any holes they overlap or touch.
+    pub fn insert(&mut self, blocks: Range<u64>) {
+        let Range { mut start, mut end } = blocks;
+        if start >= end {
+            return;
+        }
+
+        // The range ending at or past `start` is absorbed, along with those starting up to `end`.
+        if let Some((&prev, &prev_end)) = self.ranges.range(..start).next_back() {
+            if prev_end >= start {
+                start = prev;
+                end = end.max(prev_end);
+            }
+        }
+        let absorbed: Vec<(u64, u64)> = self
+            .ranges
+            .range(start..=end)
+            .map(|(&start, &end)| (start, end))
+            .collect();
+        for (next, next_end) in absorbed {
+            self.ranges.remove(&next);
+            end = end.max(next_end);
+        }
+
+        self.ranges.insert(start, end);
+    }
+
+    /// Fills in `blocks`, splitting any holes that extend past them.
+    pub fn remove(&mut self, blocks: Range<u64>) {
+        let Range { start, end } = blocks;
+        if start >= end {
+            return;
+        }
+
+        // A range starting before `start` keeps its head, and its tail if it extends past `end`.
+        if let Some((&prev, &prev_end)) = self.ranges.range(..start).next_back() {
+            if prev_end > start {
+                self.ranges.insert(prev, start);
+                if prev_end > end {
+                    self.ranges.insert(end, prev_end);
+                }
+            }
+        }
+
+        let overlapping: Vec<(u64, u64)> = self
+            .ranges
+            .range(start..end)
+            .map(|(&start, &end)| (start, end))
+            .collect();
+        for (next, next_end) in overlapping {
+            self.ranges.remove(&next);
+            if next_end > end {
+                self.ranges.insert(end, next_end);
+            }
+        }
+    }
+
+    /// Fills in every hole at or past `block`.
+    pub fn truncate(&mut self, block: u64) {
+        self.remove(block..u64::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_and_split() {
+        let mut holes = Holes::new();
+        holes.insert(0..2);
+        holes.insert(4..6);
+        holes.insert(2..4);
+        assert_eq!(holes.iter().collect::<Vec<_>>(), vec![0..6]);
+
+        // Removing from the middle splits the range.
+        holes.remove(2..3);
+        assert_eq!(holes.iter().collect::<Vec<_>>(), vec![0..2, 3..6]);
+        assert_eq!(holes.containing(4), Some(3..6));
+        assert_eq!(holes.containing(2), None);
+        assert_eq!(
+            holes.overlapping(1..4).collect::<Vec<_>>(),
+            vec![1..2, 3..4]
+        );
+
+        // Inserting over several ranges merges them all.
+        holes.insert(1..10);
+        assert_eq!(holes.iter().collect::<Vec<_>>(), vec![0..10]);
+
+        holes.truncate(5);
+        assert_eq!(holes.iter().collect::<Vec<_>>(), vec![0..5]);
+        holes.remove(0..5);
+        assert!(holes.is_empty());
+    }
+
+    // Huge holes take a single entry.
+    #[test]
+    fn huge() {
+        let mut holes = Holes::new();
+        holes.insert(1..1 << 40);
+        holes.remove(1 << 20..(1 << 20) + 1);
+        assert_eq!(
+            holes.iter().collect::<Vec<_>>(),
+            vec![1..1 << 20, (1 << 20) + 1..1 << 40]
+        );
+    }
+}
@@ -1,7 +1,13 @@
 mod blockcrypt;
 mod crypt;
+mod holes;
+mod object;
+mod readonly;
 mod recrypt;
 
 pub use blockcrypt::BlockCryptIo;
 pub use crypt::CryptIo;
+pub use holes::Holes;
+pub use object::ObjectIo;
+pub use readonly::ReadOnlyIo;
 pub use recrypt::BlockRecryptIo;
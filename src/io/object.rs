@@ -0,0 +1,357 @@
+use super::Holes;
+use crate::options::PaddingPolicy;
+use embedded_io::{
+    blocking::{Read, Seek, Write},
+    Io, SeekFrom,
+};
+
+/// Presents an object at its logical length, even though the underlying `IO` may be padded out
+/// past it according to a `PaddingPolicy`. Reads stop at the logical length, seeks relative to
+/// the end are relative to the logical length, and writes that extend the object extend the
+/// padding along with it.
+///
+/// Blocks in `holes` read back as zeros, whatever the underlying `IO` holds for them. Writing to
+/// a hole fills it in, and writing past the end of the object leaves the blocks it skips over as
+/// holes.
+pub struct ObjectIo<'a, IO, const BLK_SZ: usize> {
+    io: IO,
+    len: &'a mut u64,
+    holes: &'a mut Holes,
+    padding: PaddingPolicy,
+    written: Option<&'a mut u64>,
+}
+
+impl<'a, IO, const BLK_SZ: usize> ObjectIo<'a, IO, BLK_SZ> {
+    pub fn new(io: IO, len: &'a mut u64, holes: &'a mut Holes, padding: PaddingPolicy) -> Self {
+        Self {
+            io,
+            len,
+            holes,
+            padding,
+            written: None,
+        }
+    }
+
+    /// Adds the number of bytes written through this handle to `written`.
+    pub fn count_writes(mut self, written: &'a mut u64) -> Self {
+        self.written = Some(written);
+        self
+    }
+
+    /// Returns the logical length of the object.
+    pub fn len(&self) -> u64 {
+        *self.len
+    }
+
+    /// Returns `true` if the object is logically empty.
+    pub fn is_empty(&self) -> bool {
+        *self.len == 0
+    }
+
+    /// Returns the offset of the first data at or after `offset`, like `SEEK_DATA`. Returns
+    /// `None` if there are only holes from `offset` to the end of the object.
+    pub fn next_data(&self, offset: u64) -> Option<u64> {
+        let blk_sz = BLK_SZ as u64;
+
+        let block = offset / blk_sz;
+        let block = self.holes.containing(block).map_or(block, |hole| hole.end);
+
+        let data = (block * blk_sz).max(offset);
+        (data < *self.len).then_some(data)
+    }
+
+    /// Returns the offset of the first hole at or after `offset`, like `SEEK_HOLE`. The end of the
+    /// object counts as a hole. Returns `None` if `offset` is past the end of the object.
+    pub fn next_hole(&self, offset: u64) -> Option<u64> {
+        if offset >= *self.len {
+            return None;
+        }
+
+        let blk_sz = BLK_SZ as u64;
+        let hole = self
+            .holes
+            .overlapping(offset / blk_sz..u64::MAX)
+            .next()
+            .map_or(*self.len, |hole| (hole.start * blk_sz).max(offset));
+
+        Some(hole.min(*self.len))
+    }
+}
+
+impl<IO, const BLK_SZ: usize> ObjectIo<'_, IO, BLK_SZ>
+where
+    IO: Write + Seek,
+{
+    /// Writes zeros from the end of the underlying `IO` up to the padded length. The position
+    /// is left at the end of the padding.
+    pub fn pad(&mut self) -> Result<(), IO::Error> {
+        let padded = self.padding.padded_len(*self.len, BLK_SZ as u64);
+        let physical = self.io.seek(SeekFrom::End(0))?;
+        self.write_zeros(padded.saturating_sub(physical))
+    }
+
+    // Writes `n` zeros at the current position.
+    fn write_zeros(&mut self, mut n: u64) -> Result<(), IO::Error> {
+        let zeros = [0; BLK_SZ];
+        while n > 0 {
+            let written = self.io.write(&zeros[..n.min(BLK_SZ as u64) as usize])?;
+            if written == 0 {
+                break;
+            }
+            n -= written as u64;
+        }
+        Ok(())
+    }
+
+    // Fills in the holes overlapping `start..end` ahead of a write to that range. Holes that the
+    // write covers entirely don't need their old contents, so they're just forgotten. Only the
+    // blocks at either edge of the write can be partially covered.
+    fn fill_holes(&mut self, start: u64, end: u64) -> Result<(), IO::Error> {
+        let blk_sz = BLK_SZ as u64;
+        let blocks = start / blk_sz..end.div_ceil(blk_sz);
+        if start >= end || self.holes.overlapping(blocks.clone()).next().is_none() {
+            return Ok(());
+        }
+
+        let physical = self.io.seek(SeekFrom::End(0))?;
+        let (first, last) = (blocks.start, blocks.end - 1);
+        for block in [first, last] {
+            let offset = block * blk_sz;
+            let partial = offset < start || offset + blk_sz > end;
+            if partial && self.holes.containing(block).is_some() {
+                self.io.seek(SeekFrom::Start(offset))?;
+                self.write_zeros(physical.saturating_sub(offset).min(blk_sz))?;
+            }
+            if first == last {
+                break;
+            }
+        }
+        self.holes.remove(blocks);
+
+        self.io.seek(SeekFrom::Start(start))?;
+        Ok(())
+    }
+
+    // Prepares for a write at `pos`, past the end of the object. Everything between the end and
+    // `pos` must read back as zeros. Up to the physical end that's already the case, since it's
+    // either padding or holes. Past that, whole blocks are recorded as holes rather than written,
+    // and only the partial blocks at either edge are filled in with zeros.
+    fn extend(&mut self, pos: u64) -> Result<(), IO::Error> {
+        let blk_sz = BLK_SZ as u64;
+
+        let physical = self.io.seek(SeekFrom::End(0))?;
+        if pos <= physical {
+            return Ok(());
+        }
+
+        // Fill in the rest of the block holding the physical end.
+        let first = physical.div_ceil(blk_sz);
+        let gap_end = (first * blk_sz).min(pos);
+        self.write_zeros(gap_end - physical)?;
+
+        // Skip over the whole blocks.
+        let last = pos / blk_sz;
+        self.holes.insert(first..last);
+
+        // Fill in the start of the block being written to.
+        let start = (last * blk_sz).max(gap_end);
+        if start < pos {
+            self.io.seek(SeekFrom::Start(start))?;
+            self.write_zeros(pos - start)?;
+        }
+
+        self.io.seek(SeekFrom::Start(pos))?;
+        Ok(())
+    }
+}
+
+impl<IO, const BLK_SZ: usize> Io for ObjectIo<'_, IO, BLK_SZ>
+where
+    IO: Io,
+{
+    type Error = IO::Error;
+}
+
+impl<IO, const BLK_SZ: usize> Read for ObjectIo<'_, IO, BLK_SZ>
+where
+    IO: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let pos = self.io.stream_position()?;
+        let n = buf.len().min(self.len.saturating_sub(pos) as usize);
+        if n == 0 {
+            return Ok(0);
+        }
+        let n = self.io.read(&mut buf[..n])?;
+
+        // Blank out whatever the underlying `IO` holds for holes.
+        let blk_sz = BLK_SZ as u64;
+        let end = pos + n as u64;
+        for hole in self.holes.overlapping(pos / blk_sz..end.div_ceil(blk_sz)) {
+            let lo = (hole.start * blk_sz).max(pos) - pos;
+            let hi = (hole.end * blk_sz).min(end) - pos;
+            buf[lo as usize..hi as usize].fill(0);
+        }
+
+        Ok(n)
+    }
+}
+
+impl<IO, const BLK_SZ: usize> Write for ObjectIo<'_, IO, BLK_SZ>
+where
+    IO: Write + Seek,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let pos = self.io.stream_position()?;
+        if pos > *self.len {
+            self.extend(pos)?;
+        }
+        self.fill_holes(pos, pos + buf.len() as u64)?;
+        let n = self.io.write(buf)?;
+        if let Some(written) = self.written.as_deref_mut() {
+            *written += n as u64;
+        }
+
+        let end = pos + n as u64;
+        if end > *self.len {
+            *self.len = end;
+            self.pad()?;
+            self.io.seek(SeekFrom::Start(end))?;
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.io.flush()
+    }
+}
+
+impl<IO, const BLK_SZ: usize> Seek for ObjectIo<'_, IO, BLK_SZ>
+where
+    IO: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        match pos {
+            // Translate to an offset from the physical end, so the underlying `IO` is the one to
+            // reject seeks before the start.
+            SeekFrom::End(offset) => {
+                let physical = self.io.seek(SeekFrom::End(0))?;
+                self.io
+                    .seek(SeekFrom::End(offset + *self.len as i64 - physical as i64))
+            }
+            pos => self.io.seek(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use embedded_io::adapters::FromStd;
+    use tempfile::NamedTempFile;
+
+    const BLOCK_SIZE: usize = 4096;
+
+    #[test]
+    fn padded_write() -> Result<()> {
+        let mut len = 0;
+        let mut holes = Holes::new();
+        let mut io = ObjectIo::<_, BLOCK_SIZE>::new(
+            FromStd::new(NamedTempFile::new()?),
+            &mut len,
+            &mut holes,
+            PaddingPolicy::PowerOfTwo,
+        );
+
+        io.write_all(&['a' as u8; 5000])?;
+        assert_eq!(io.stream_position()?, 5000);
+        assert_eq!(io.seek(SeekFrom::End(0))?, 5000);
+        assert_eq!(io.len(), 5000);
+
+        // Reads stop at the logical length.
+        let mut buf = vec![0; 8192];
+        io.seek(SeekFrom::Start(0))?;
+        assert_eq!(io.read(&mut buf)?, 5000);
+
+        let file = io.io.into_inner();
+        assert_eq!(file.as_file().metadata()?.len(), 8192);
+
+        Ok(())
+    }
+
+    #[test]
+    fn holes() -> Result<()> {
+        let mut len = 0;
+        let mut holes = Holes::new();
+        let mut io = ObjectIo::<_, BLOCK_SIZE>::new(
+            FromStd::new(NamedTempFile::new()?),
+            &mut len,
+            &mut holes,
+            PaddingPolicy::None,
+        );
+        io.write_all(&['a' as u8; 3 * BLOCK_SIZE])?;
+        io.holes.insert(1..3);
+
+        let mut buf = vec![0; 3 * BLOCK_SIZE];
+        io.seek(SeekFrom::Start(0))?;
+        io.read_exact(&mut buf)?;
+        assert_eq!(&buf[..BLOCK_SIZE], &['a' as u8; BLOCK_SIZE]);
+        assert_eq!(&buf[BLOCK_SIZE..], &[0; 2 * BLOCK_SIZE]);
+
+        // A partial write fills in the rest of the hole with zeros.
+        io.seek(SeekFrom::Start(BLOCK_SIZE as u64 + 10))?;
+        io.write_all(&['b' as u8; 10])?;
+        assert_eq!(io.holes.iter().collect::<Vec<_>>(), vec![2..3]);
+
+        io.seek(SeekFrom::Start(BLOCK_SIZE as u64))?;
+        io.read_exact(&mut buf[..BLOCK_SIZE])?;
+        assert_eq!(&buf[..10], &[0; 10]);
+        assert_eq!(&buf[10..20], &['b' as u8; 10]);
+        assert_eq!(&buf[20..BLOCK_SIZE], &[0; BLOCK_SIZE - 20]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sparse() -> Result<()> {
+        let mut len = 0;
+        let mut holes = Holes::new();
+        let mut io = ObjectIo::<_, BLOCK_SIZE>::new(
+            FromStd::new(NamedTempFile::new()?),
+            &mut len,
+            &mut holes,
+            PaddingPolicy::None,
+        );
+        io.write_all(&['a' as u8; 100])?;
+        io.seek(SeekFrom::Start(3 * BLOCK_SIZE as u64 + 10))?;
+        io.write_all(&['b' as u8; 10])?;
+
+        assert_eq!(io.holes.iter().collect::<Vec<_>>(), vec![1..3]);
+        assert_eq!(io.next_data(0), Some(0));
+        assert_eq!(io.next_hole(0), Some(BLOCK_SIZE as u64));
+        assert_eq!(io.next_data(BLOCK_SIZE as u64), Some(3 * BLOCK_SIZE as u64));
+        assert_eq!(io.next_hole(3 * BLOCK_SIZE as u64), Some(io.len()));
+        assert_eq!(io.next_data(io.len()), None);
+        assert_eq!(io.next_hole(io.len()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn policies() {
+        let block = BLOCK_SIZE as u64;
+
+        assert_eq!(PaddingPolicy::None.padded_len(5000, block), 5000);
+        assert_eq!(PaddingPolicy::Block.padded_len(5000, block), 8192);
+        assert_eq!(PaddingPolicy::Block.padded_len(0, block), 0);
+        assert_eq!(PaddingPolicy::PowerOfTwo.padded_len(5000, block), 8192);
+        assert_eq!(PaddingPolicy::PowerOfTwo.padded_len(0, block), 0);
+        assert_eq!(PaddingPolicy::Fixed(1 << 20).padded_len(0, block), 1 << 20);
+        assert_eq!(
+            PaddingPolicy::Fixed(1 << 20).padded_len((1 << 20) + 1, block),
+            2 << 20
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use embedded_io::{
+    blocking::{Read, Seek},
+    Io, SeekFrom,
+};
+
+/// Restricts an `IO` to reading and seeking.
+pub struct ReadOnlyIo<IO> {
+    io: IO,
+}
+
+impl<IO> ReadOnlyIo<IO> {
+    pub fn new(io: IO) -> Self {
+        Self { io }
+    }
+}
+
+impl<IO> Io for ReadOnlyIo<IO>
+where
+    IO: Io,
+{
+    type Error = IO::Error;
+}
+
+impl<IO> Read for ReadOnlyIo<IO>
+where
+    IO: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.io.read(buf)
+    }
+}
+
+impl<IO> Seek for ReadOnlyIo<IO>
+where
+    IO: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        self.io.seek(pos)
+    }
+}
@@ -6,6 +6,7 @@ use embedded_io::{
 };
 use kms::KeyManagementScheme;
 use std::marker::PhantomData;
+use zeroize::Zeroize;
 
 pub struct BlockRecryptIo<'a, IO, CKMS, NKMS, C, const BLK_SZ: usize, const KEY_SZ: usize> {
     io: IO,
@@ -68,10 +69,12 @@ where
                 return Ok(0);
             }
 
-            let key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
-            let tmp_buf = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            let mut key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
+            let mut tmp_buf = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            key.zeroize();
 
             buf[..actually_read].copy_from_slice(&tmp_buf[fill..fill + actually_read]);
+            tmp_buf.zeroize();
 
             offset += actually_read;
             total += actually_read;
@@ -94,10 +97,12 @@ where
                 return Ok(total);
             }
 
-            let key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
-            let tmp_buf = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            let mut key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
+            let mut tmp_buf = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            key.zeroize();
 
             buf[total..total + nbytes].copy_from_slice(&tmp_buf[..nbytes]);
+            tmp_buf.zeroize();
 
             offset += nbytes;
             size -= nbytes;
@@ -144,13 +149,18 @@ where
                 return Ok(0);
             }
 
-            let key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
-            let mut tmp_buf = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            let mut key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
+            let mut plaintext = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            key.zeroize();
 
-            tmp_buf[fill..fill + rest].copy_from_slice(&buf[..rest]);
+            plaintext[fill..fill + rest].copy_from_slice(&buf[..rest]);
 
-            let key = self.next_kms.derive(block as u64).map_err(|_| ()).unwrap();
-            let tmp_buf = C::onetime_encrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            let mut key = self.next_kms.derive(block as u64).map_err(|_| ()).unwrap();
+            let tmp_buf = C::onetime_encrypt(&key, &plaintext)
+                .map_err(|_| ())
+                .unwrap();
+            key.zeroize();
+            plaintext.zeroize();
 
             let amount = nbytes.max(fill + rest);
             self.io.seek(SeekFrom::Start(off as u64))?;
@@ -171,10 +181,11 @@ where
         // block-by-block.
         while size > 0 && size / BLK_SZ > 0 && offset % BLK_SZ == 0 {
             let block = offset / BLK_SZ;
-            let key = self.next_kms.derive(block as u64).map_err(|_| ()).unwrap();
+            let mut key = self.next_kms.derive(block as u64).map_err(|_| ()).unwrap();
             let tmp_buf = C::onetime_encrypt(&key, &buf[total..total + BLK_SZ])
                 .map_err(|_| ())
                 .unwrap();
+            key.zeroize();
 
             let off = block * BLK_SZ;
             self.io.seek(SeekFrom::Start(off as u64))?;
@@ -201,13 +212,18 @@ where
             let actually_read = self.io.read(&mut tmp_buf)?;
             let actually_write = size.max(actually_read);
 
-            let key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
-            let mut tmp_buf = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            let mut key = self.curr_kms.derive(block as u64).map_err(|_| ()).unwrap();
+            let mut plaintext = C::onetime_decrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            key.zeroize();
 
-            tmp_buf[..size].copy_from_slice(&buf[total..total + size]);
+            plaintext[..size].copy_from_slice(&buf[total..total + size]);
 
-            let key = self.next_kms.derive(block as u64).map_err(|_| ()).unwrap();
-            let tmp_buf = C::onetime_encrypt(&key, &tmp_buf).map_err(|_| ()).unwrap();
+            let mut key = self.next_kms.derive(block as u64).map_err(|_| ()).unwrap();
+            let tmp_buf = C::onetime_encrypt(&key, &plaintext)
+                .map_err(|_| ())
+                .unwrap();
+            key.zeroize();
+            plaintext.zeroize();
 
             self.io.seek(SeekFrom::Start(off as u64))?;
             let nbytes = self.io.write(&tmp_buf[..actually_write])?;
@@ -1,6 +1,16 @@
+pub mod alloc;
+pub mod enclave;
 pub mod error;
 pub mod io;
+mod memlock;
+mod metadata;
+pub mod namespace;
+pub mod options;
 pub mod result;
+pub mod scheme;
+mod snapshot;
+pub mod stats;
+pub mod transaction;
 
 use allocator::Allocator;
 use crypter::Crypter;
@@ -8,15 +18,29 @@ use embedded_io::{
     blocking::{Read, Seek, Write},
     SeekFrom,
 };
+use enclave::Enclave;
 use error::Error;
 use hasher::Hasher;
-use io::{BlockCryptIo, BlockRecryptIo, CryptIo};
+use io::{BlockCryptIo, BlockRecryptIo, CryptIo, ObjectIo, ReadOnlyIo};
 use khf::{Consolidation, Khf};
 use kms::KeyManagementScheme;
-use persistence::{Persist, PersistentStorage};
+use memlock::KeyPage;
+use metadata::Metadata;
+use options::{AutoPersist, ObjectOptions, PaddingPolicy};
+use persistence::PersistentStorage;
 use rand::{CryptoRng, RngCore};
+use scheme::LetheKms;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, marker::PhantomData};
+use snapshot::{Snapshot, SnapshotObject};
+use stats::{LetheStats, ObjectStats};
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    ops::RangeBounds,
+    time::{Duration, Instant, SystemTime},
+};
+use transaction::Transaction;
+use zeroize::{Zeroize, Zeroizing};
 
 pub(crate) type Key<const N: usize> = [u8; N];
 
@@ -24,86 +48,158 @@ pub(crate) type Key<const N: usize> = [u8; N];
 const DEFAULT_MASTER_KHF_FANOUTS: &[u64; 4] = &[4, 4, 4, 4];
 const DEFAULT_OBJECT_KHF_FANOUTS: &[u64; 4] = &[4, 4, 4, 4];
 
+// Blocks copied at a time between objects. Handles borrow the storage mutably, so the source and
+// destination of a copy can't both be open at once; copying in chunks keeps reopening them rare.
+const COPY_CHUNK_BLOCKS: usize = 64;
+
 // Reserved object IDs.
 const MASTER_KHF_OBJID: u64 = 0;
 const OBJECT_KHF_FANOUTS_OBJID: u64 = 1;
 const ALLOCATOR_OBJID: u64 = 2;
 const MAPPINGS_OBJID: u64 = 3;
-
-pub struct Lethe<S, P, A, R, C, H, const E: usize, const D: usize>
-where
-    S: Read + Write + Seek,
+const STATE_OBJID: u64 = 4;
+// Marks a successfully decrypted `State`.
+const STATE_MAGIC: u64 = u64::from_le_bytes(*b"lethe\0\0\x01");
+
+const RESERVED_OBJIDS: &[u64; 5] = &[
+    MASTER_KHF_OBJID,
+    OBJECT_KHF_FANOUTS_OBJID,
+    ALLOCATOR_OBJID,
+    MAPPINGS_OBJID,
+    STATE_OBJID,
+];
+
+pub struct Lethe<
+    S,
+    P,
+    A,
+    R,
+    C,
+    H,
+    const E: usize,
+    const D: usize,
+    K = Khf<R, H, E>,
+    M = Khf<R, H, E>,
+> where
+    S: Enclave,
     P: PersistentStorage<Id = u64>,
-    <P as PersistentStorage>::Flags: Default,
+    for<'a> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'a>,
     for<'a> <P as PersistentStorage>::Io<'a>: Read + Write + Seek,
     for<'a> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'a>,
     R: RngCore + CryptoRng + Clone + Default,
     C: Crypter,
     H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
 {
-    master_key: Key<E>,
-    master_khf: Khf<R, H, E>,
-    object_khfs: HashMap<u64, Khf<R, H, E>>,
+    // In a page of its own, so that it can be locked in memory.
+    master_key: KeyPage<E>,
+    // Unlike the master key, the `Khf`s aren't locked in memory or zeroized when dropped.
+    master_khf: M,
+    object_khfs: HashMap<u64, K>,
     object_khf_fanouts: Vec<u64>,
+    metadata:
+        HashMap<u64, Metadata<<P as PersistentStorage>::Info, <P as PersistentStorage>::Flags>>,
+    snapshots:
+        HashMap<u64, Snapshot<K, <P as PersistentStorage>::Info, <P as PersistentStorage>::Flags>>,
+    next_snapshot: u64,
+    padding: PaddingPolicy,
+    grace_period: Option<u64>,
+    trash: BTreeMap<u64, Trashed>,
+    // Expiry deadlines, keyed by metadata ID so they follow objects through renames.
+    expiries: BTreeMap<u64, SystemTime>,
+    auto_persist: AutoPersist,
+    auto_persist_suspended: bool,
+    pending: Pending,
+    last_commit: Instant,
     allocator: A,
-    mappings: HashMap<u64, MapEntry>,
+    mappings: BTreeMap<u64, MapEntry>,
+    epoch: u64,
     enclave: S,
     pub storage: P,
-    pd: PhantomData<C>,
+    pd: PhantomData<(C, H)>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MapEntry {
     pub map_id: u64,
     pub khf_id: u64,
+    pub meta_id: u64,
+    /// The fanouts of the object's `Khf`.
+    pub fanouts: Vec<u64>,
+    /// The epoch in which the object was last updated.
+    pub epoch: u64,
 }
 
-impl<S, P, A, R, C, H, const E: usize, const D: usize> Lethe<S, P, A, R, C, H, E, D>
+/// Describes an object, as returned by `Lethe::objects()` and `Lethe::iter_objects()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectEntry {
+    pub objid: u64,
+    pub map_id: u64,
+    pub khf_id: u64,
+    pub meta_id: u64,
+    /// The logical length of the object in bytes.
+    pub size: u64,
+}
+
+// Miscellaneous state persisted alongside the master `Khf`.
+#[derive(Serialize, Deserialize)]
+struct State {
+    magic: u64,
+    epoch: u64,
+    // The value the enclave's monotonic counter has once this state is committed.
+    counter: u64,
+    // The storage IDs of snapshot copies that were live at the time. Snapshots don't survive a
+    // reload, so these are destroyed when the state is loaded.
+    snapshots: Vec<u64>,
+    trash: BTreeMap<u64, Trashed>,
+    expiries: BTreeMap<u64, SystemTime>,
+    padding: PaddingPolicy,
+}
+
+// Changes made since the last commit, counted against the auto-persist thresholds.
+#[derive(Default)]
+struct Pending {
+    ops: u64,
+    bytes: u64,
+    destroys: u64,
+    // Whether keys have been updated to forget something, which only takes effect once
+    // committed. Operations that don't count, like emptying the trash, still set this.
+    forgotten: bool,
+}
+
+// An object that was destroyed in grace mode, along with the epoch it was destroyed in.
+#[derive(Clone, Serialize, Deserialize)]
+struct Trashed {
+    entry: MapEntry,
+    epoch: u64,
+}
+
+impl<S, P, A, R, C, H, const E: usize, const D: usize, K, M> Lethe<S, P, A, R, C, H, E, D, K, M>
 where
-    S: Read + Write + Seek,
+    S: Enclave,
     P: PersistentStorage<Id = u64>,
-    <P as PersistentStorage>::Flags: Default,
+    for<'a> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'a>,
     for<'a> <P as PersistentStorage>::Io<'a>: Read + Write + Seek,
     for<'a> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'a>,
     R: RngCore + CryptoRng + Clone + Default,
     C: Crypter,
     H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
 {
-    /// Creates a new `Lethe` instance.
+    /// Creates a new `Lethe` instance. Panics if the reserved objects can't be created; use
+    /// `options()` to handle that instead.
     pub fn new(enclave: S, storage: P) -> Self {
-        let mut master_key = [0; E];
-        R::default().fill_bytes(&mut master_key);
-
-        let mut lethe = Self {
-            master_key,
-            master_khf: Khf::new(DEFAULT_MASTER_KHF_FANOUTS, R::default()),
-            object_khfs: HashMap::new(),
-            object_khf_fanouts: DEFAULT_MASTER_KHF_FANOUTS.to_vec(),
-            allocator: A::default(),
-            mappings: HashMap::new(),
-            enclave,
-            storage,
-            pd: PhantomData,
-        };
-
-        for id in [
-            MASTER_KHF_OBJID,
-            OBJECT_KHF_FANOUTS_OBJID,
-            ALLOCATOR_OBJID,
-            MAPPINGS_OBJID,
-        ] {
-            lethe.allocator.reserve(id).unwrap();
-            lethe
-                .storage
-                .create(&id, &<P as PersistentStorage>::Flags::default())
-                .unwrap();
-        }
-
-        lethe
+        LetheBuilder::new().build(enclave, storage).unwrap()
     }
 
     /// Creates a new `LetheBuilder` instance.
-    pub fn options() -> LetheBuilder<S, P, A, R, C, H, E, D> {
+    pub fn options() -> LetheBuilder<S, P, A, R, C, H, E, D, K, M> {
         LetheBuilder::new()
     }
 
@@ -117,28 +213,460 @@ where
         }
 
         // Construct the `Io` to load the object `Khf`.
-        let key = self.master_khf.derive(entry.khf_id)?;
-        let io = CryptIo::<<P as PersistentStorage>::Io<'_>, C, E>::new(
+        let mut key = self.master_khf.derive(entry.khf_id)?;
+        let mut io = CryptIo::<<P as PersistentStorage>::Io<'_>, C, E>::new(
             self.storage
                 .read_handle(&entry.khf_id)
                 .map_err(|_| Error::Io)?,
             key,
         );
+        key.zeroize();
 
         // Only IO errors should prevent the object `Khf` from being loaded.
-        let khf = Khf::load(io)?;
+        let mut ser = Zeroizing::new(vec![]);
+        io.read_to_end(&mut ser).map_err(|_| Error::Io)?;
+        let khf = bincode::deserialize(&ser)?;
         self.object_khfs.insert(entry.khf_id, khf);
 
         Ok(())
     }
 
+    /// Loads persisted object metadata.
+    fn load_metadata(&mut self, objid: u64) -> Result<(), Error> {
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+
+        // If the metadata is already loaded, we're done.
+        if self.metadata.contains_key(&entry.meta_id) {
+            return Ok(());
+        }
+
+        let meta_id = entry.meta_id;
+        let metadata = self.read_metadata(meta_id)?;
+        self.metadata.insert(meta_id, metadata);
+
+        Ok(())
+    }
+
+    // Reads persisted object metadata without caching it.
+    fn read_metadata(
+        &mut self,
+        meta_id: u64,
+    ) -> Result<Metadata<<P as PersistentStorage>::Info, <P as PersistentStorage>::Flags>, Error>
+    {
+        let mut key = self.master_khf.derive(meta_id)?;
+        let mut io = CryptIo::<<P as PersistentStorage>::Io<'_>, C, E>::new(
+            self.storage.read_handle(&meta_id).map_err(|_| Error::Io)?,
+            key,
+        );
+        key.zeroize();
+
+        let mut ser = Zeroizing::new(vec![]);
+        io.read_to_end(&mut ser).map_err(|_| Error::Io)?;
+        Ok(bincode::deserialize(&ser)?)
+    }
+
+    /// Creates an object, overriding the store-wide defaults with `options`.
+    pub fn create_with_options(
+        &mut self,
+        objid: u64,
+        flags: &<P as PersistentStorage>::Flags,
+        options: &ObjectOptions,
+    ) -> Result<(), Error> {
+        self.counted(|lethe| lethe.create_object(objid, flags, options))
+    }
+
+    fn create_object(
+        &mut self,
+        objid: u64,
+        flags: &<P as PersistentStorage>::Flags,
+        options: &ObjectOptions,
+    ) -> Result<(), Error> {
+        let fanouts = options
+            .khf_fanouts
+            .clone()
+            .unwrap_or_else(|| self.object_khf_fanouts.clone());
+
+        if fanouts.is_empty() || fanouts.contains(&0) {
+            return Err(Error::InvalidFanouts);
+        }
+
+        if self.mappings.contains_key(&objid) {
+            return Err(Error::ObjectExists);
+        }
+
+        let map_id = self.allocator.alloc().map_err(|_| Error::Alloc)?;
+        let khf_id = self.allocator.alloc().map_err(|_| Error::Alloc)?;
+        let meta_id = self.allocator.alloc().map_err(|_| Error::Alloc)?;
+
+        // The fanouts are serialized as part of the object `Khf`, so they're restored when it's
+        // loaded. We keep a copy in the mapping so they can be inspected without loading it.
+        self.object_khfs.insert(khf_id, K::with_fanouts(&fanouts));
+        self.master_khf.update(khf_id)?;
+
+        let metadata = Metadata {
+            flags: flags.clone(),
+            expiry: options.expiry,
+            ..Default::default()
+        };
+        self.metadata.insert(meta_id, metadata);
+        self.master_khf.update(meta_id)?;
+        if let Some(when) = options.expiry {
+            self.expiries.insert(meta_id, when);
+        }
+
+        self.mappings.insert(
+            objid,
+            MapEntry {
+                map_id,
+                khf_id,
+                meta_id,
+                fanouts,
+                epoch: self.epoch,
+            },
+        );
+
+        self.storage
+            .create(&khf_id, &<P as PersistentStorage>::Flags::default())
+            .map_err(|_| Error::Io)?;
+        self.storage
+            .create(&meta_id, &<P as PersistentStorage>::Flags::default())
+            .map_err(|_| Error::Io)?;
+        self.storage.create(&map_id, flags).map_err(|_| Error::Io)?;
+
+        // Some padding policies pad out even empty objects.
+        if self.padding.padded_len(0, D as u64) > 0 {
+            self.truncate_object(objid, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `data` to an object at `offset`, as a single operation. Unlike writes through
+    /// `write_handle()`, whose bytes only count towards the auto-persist thresholds from the next
+    /// operation on, this checks the thresholds as soon as the write is done.
+    pub fn write_at(&mut self, objid: u64, offset: u64, data: &[u8]) -> Result<(), Error> {
+        self.counted(|lethe| {
+            let mut io = lethe.open_object(objid)?;
+            io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+            io.write_all(data).map_err(|_| Error::Io)
+        })
+    }
+
+    /// Securely shreds `len` bytes of an object, starting at `offset`, without shortening it.
+    /// The range reads back as zeros, and its old contents are unrecoverable once the next
+    /// `persist_state()` completes.
+    pub fn shred_range(&mut self, objid: u64, offset: u64, len: u64) -> Result<(), Error> {
+        self.counted(|lethe| lethe.shred_object(objid, offset, len))
+    }
+
+    fn shred_object(&mut self, objid: u64, offset: u64, len: u64) -> Result<(), Error> {
+        let mut io = self.open_object(objid)?;
+        let end = offset.saturating_add(len).min(io.len());
+        if offset >= end {
+            return Ok(());
+        }
+
+        // Blocks entirely within the range become holes. The last block may be a partial one.
+        let first = offset.div_ceil(D as u64);
+        let last = if end == io.len() {
+            end.div_ceil(D as u64)
+        } else {
+            end / D as u64
+        };
+
+        // The partially covered blocks at the edges of the range are overwritten with zeros,
+        // which rewrites them under fresh keys.
+        let edges = if first < last {
+            [(offset, first * D as u64), (last * D as u64, end)]
+        } else {
+            [(offset, end), (end, end)]
+        };
+        for (start, stop) in edges {
+            if start < stop {
+                io.seek(SeekFrom::Start(start)).map_err(|_| Error::Io)?;
+                io.write_all(&vec![0; (stop - start) as usize])
+                    .map_err(|_| Error::Io)?;
+            }
+        }
+        drop(io);
+
+        // The blocks that become holes aren't rewritten. Fresh keys are enough to make their old
+        // contents unrecoverable.
+        let khf = self.get_khf_mut(objid)?.ok_or(Error::MissingKhf)?;
+        for block in first..last {
+            khf.update(block)?;
+        }
+        self.pending.forgotten = true;
+
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+        self.metadata
+            .get_mut(&entry.meta_id)
+            .unwrap()
+            .holes
+            .insert(first..last);
+
+        Ok(())
+    }
+
+    // Opens a handle for writing to an object, without counting it as an operation.
+    fn open_object(
+        &mut self,
+        objid: u64,
+    ) -> Result<ObjectIo<'_, BlockCryptIo<'_, P::Io<'_>, K, C, D, E>, D>, Error> {
+        self.load_khf(objid)?;
+        self.load_metadata(objid)?;
+
+        let entry = self.mappings.get_mut(&objid).ok_or(Error::MissingKhf)?;
+        entry.epoch = self.epoch;
+        let khf = self
+            .object_khfs
+            .get_mut(&entry.khf_id)
+            .ok_or(Error::MissingKhf)?;
+        let metadata = self
+            .metadata
+            .get_mut(&entry.meta_id)
+            .ok_or(Error::MissingKhf)?;
+        let io = self
+            .storage
+            .rw_handle(&entry.map_id)
+            .map_err(|_| Error::Io)?;
+
+        // Writes may extend the logical length kept in the metadata.
+        self.master_khf.update(entry.khf_id)?;
+        self.master_khf.update(entry.meta_id)?;
+
+        Ok(ObjectIo::new(
+            BlockCryptIo::new(io, khf),
+            &mut metadata.len,
+            &mut metadata.holes,
+            self.padding,
+        )
+        .count_writes(&mut self.pending.bytes))
+    }
+
+    fn truncate_object(&mut self, objid: u64, size: u64) -> Result<(), Error> {
+        // Opening a write handle loads the object and marks it as updated.
+        let len = self.open_object(objid)?.len();
+
+        if size < len {
+            // Number of bytes past a block.
+            let extra = size % D as u64;
+
+            // Need to rewrite the extra bytes.
+            if extra > 0 {
+                let mut io = self.open_object(objid)?;
+                let mut buf = vec![0; extra as usize];
+                let offset = (size / D as u64) * D as u64;
+
+                // Read in the extra bytes.
+                io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+                io.read(&mut buf).map_err(|_| Error::Io)?;
+
+                // Write the extra bytes.
+                io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+                io.write(&buf).map_err(|_| Error::Io)?;
+            }
+
+            // Truncate the forest. Not needed for security, but nice for efficiency.
+            let keys = (size + (D as u64 - 1)) / D as u64;
+            self.get_khf_mut(objid)?
+                .ok_or(Error::MissingKhf)?
+                .truncate(keys);
+            self.pending.forgotten = true;
+
+            // Truncate the object itself, along with any holes past the new end.
+            let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+            self.metadata
+                .get_mut(&entry.meta_id)
+                .unwrap()
+                .holes
+                .truncate(keys);
+            self.storage
+                .truncate(&entry.map_id, size)
+                .map_err(|_| Error::Io)?;
+        }
+
+        // Pad the object back out, or extend it, with encrypted zeros.
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+        self.metadata.get_mut(&entry.meta_id).unwrap().len = size;
+        self.open_object(objid)?.pad().map_err(|_| Error::Io)
+    }
+
+    /// Returns the offset of the first data in an object at or after `offset`, like `SEEK_DATA`.
+    /// Returns `None` if there are only holes from `offset` to the end of the object.
+    pub fn seek_data(&mut self, objid: u64, offset: u64) -> Result<Option<u64>, Error> {
+        Ok(self.read_handle(&objid)?.next_data(offset))
+    }
+
+    /// Returns the offset of the first hole in an object at or after `offset`, like `SEEK_HOLE`.
+    /// The end of the object counts as a hole. Returns `None` if `offset` is past the end of the
+    /// object.
+    pub fn seek_hole(&mut self, objid: u64, offset: u64) -> Result<Option<u64>, Error> {
+        Ok(self.read_handle(&objid)?.next_hole(offset))
+    }
+
+    /// Copies the raw contents of one underlying object to another, a chunk at a time.
+    fn copy_raw(&mut self, from: u64, to: u64) -> Result<(), Error> {
+        let len = self.raw_len(from)?;
+        let mut buf = vec![0; COPY_CHUNK_BLOCKS * D];
+        let mut offset = 0;
+
+        while offset < len {
+            let n = (len - offset).min(buf.len() as u64) as usize;
+
+            let mut io = self.storage.read_handle(&from).map_err(|_| Error::Io)?;
+            io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+            io.read_exact(&mut buf[..n]).map_err(|_| Error::Io)?;
+
+            let mut io = self.storage.write_handle(&to).map_err(|_| Error::Io)?;
+            io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+            io.write_all(&buf[..n]).map_err(|_| Error::Io)?;
+
+            offset += n as u64;
+        }
+
+        Ok(())
+    }
+
+    // The length of an underlying object, padding included.
+    fn raw_len(&mut self, id: u64) -> Result<u64, Error> {
+        self.storage
+            .read_handle(&id)
+            .map_err(|_| Error::Io)?
+            .seek(SeekFrom::End(0))
+            .map_err(|_| Error::Io)
+    }
+
+    fn snapshot_object(
+        &mut self,
+        objid: u64,
+    ) -> Result<
+        SnapshotObject<K, <P as PersistentStorage>::Info, <P as PersistentStorage>::Flags>,
+        Error,
+    > {
+        self.load_khf(objid)?;
+        self.load_metadata(objid)?;
+
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+        let (from, khf, metadata) = (
+            entry.map_id,
+            self.object_khfs[&entry.khf_id].clone(),
+            self.metadata[&entry.meta_id].clone(),
+        );
+
+        let map_id = self.allocator.alloc().map_err(|_| Error::Alloc)?;
+        self.storage
+            .create(&map_id, &metadata.flags)
+            .map_err(|_| Error::Io)?;
+        self.copy_raw(from, map_id)?;
+
+        Ok(SnapshotObject {
+            map_id,
+            khf,
+            metadata,
+        })
+    }
+
+    fn insert_snapshot(
+        &mut self,
+        objects: HashMap<
+            u64,
+            SnapshotObject<K, <P as PersistentStorage>::Info, <P as PersistentStorage>::Flags>,
+        >,
+    ) -> u64 {
+        let id = self.next_snapshot;
+        self.next_snapshot += 1;
+        self.snapshots.insert(id, Snapshot { objects });
+        id
+    }
+
+    /// Takes a read-only snapshot of an object, returning its ID. The snapshot keeps seeing the
+    /// object as it is now, regardless of later writes, until it's released.
+    ///
+    /// Blocks aren't shared with the object: its ciphertext is copied in full, along with its
+    /// `Khf` and metadata, so a snapshot costs as much time and space as the object itself.
+    pub fn snapshot(&mut self, objid: u64) -> Result<u64, Error> {
+        let object = self.snapshot_object(objid)?;
+        Ok(self.insert_snapshot(HashMap::from([(objid, object)])))
+    }
+
+    /// Takes a read-only snapshot of every object in the store, returning its ID. Since each
+    /// object is copied as with `snapshot()`, this reads and writes the whole store, and needs as
+    /// much free space again.
+    pub fn snapshot_all(&mut self) -> Result<u64, Error> {
+        let objids: Vec<u64> = self.mappings.keys().copied().collect();
+
+        let mut objects = HashMap::new();
+        for objid in objids {
+            match self.snapshot_object(objid) {
+                Ok(object) => {
+                    objects.insert(objid, object);
+                }
+                Err(err) => {
+                    // Don't leave the copies made so far behind.
+                    let snapshot = self.insert_snapshot(objects);
+                    self.release_snapshot(snapshot)?;
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(self.insert_snapshot(objects))
+    }
+
+    /// Returns a read handle to an object as it was when `snapshot` was taken.
+    pub fn snapshot_handle(
+        &mut self,
+        snapshot: u64,
+        objid: u64,
+    ) -> Result<ReadOnlyIo<<Self as PersistentStorage>::Io<'_>>, Error> {
+        let object = self
+            .snapshots
+            .get_mut(&snapshot)
+            .ok_or(Error::MissingSnapshot)?
+            .objects
+            .get_mut(&objid)
+            .ok_or(Error::MissingKhf)?;
+        let io = self
+            .storage
+            .read_handle(&object.map_id)
+            .map_err(|_| Error::Io)?;
+
+        Ok(ReadOnlyIo::new(ObjectIo::new(
+            BlockCryptIo::new(io, &mut object.khf),
+            &mut object.metadata.len,
+            &mut object.metadata.holes,
+            self.padding,
+        )))
+    }
+
+    /// Releases a snapshot. The keys it retained are dropped from memory, and its copies of the
+    /// objects are destroyed.
+    pub fn release_snapshot(&mut self, snapshot: u64) -> Result<(), Error> {
+        let snapshot = self
+            .snapshots
+            .remove(&snapshot)
+            .ok_or(Error::MissingSnapshot)?;
+
+        for object in snapshot.objects.into_values() {
+            self.allocator
+                .dealloc(object.map_id)
+                .map_err(|_| Error::Dealloc)?;
+            self.storage
+                .destroy(&object.map_id)
+                .map_err(|_| Error::Io)?;
+        }
+
+        Ok(())
+    }
+
     /// Returns the mapping of an object ID.
     pub fn get_khf_mapping(&self, objid: u64) -> Option<&MapEntry> {
         self.mappings.get(&objid)
     }
 
     /// Returns an immutable reference to an object `Khf`.
-    pub fn get_khf(&mut self, objid: u64) -> Result<Option<&Khf<R, H, E>>, Error> {
+    pub fn get_khf(&mut self, objid: u64) -> Result<Option<&K>, Error> {
         self.load_khf(objid)?;
         Ok(self
             .mappings
@@ -148,7 +676,7 @@ where
     }
 
     /// Returns a mutable reference to an object `Khf`.
-    pub fn get_khf_mut(&mut self, objid: u64) -> Result<Option<&mut Khf<R, H, E>>, Error> {
+    pub fn get_khf_mut(&mut self, objid: u64) -> Result<Option<&mut K>, Error> {
         self.load_khf(objid)?;
         Ok(self
             .mappings
@@ -157,8 +685,14 @@ where
             .flatten())
     }
 
+    /// Returns a mutable reference to the enclave, e.g. to change the passphrase it's wrapped
+    /// under.
+    pub fn enclave_mut(&mut self) -> &mut S {
+        &mut self.enclave
+    }
+
     /// Returns an immutable reference to the master `Khf`.
-    pub fn get_master_khf(&self) -> &Khf<R, H, E> {
+    pub fn get_master_khf(&self) -> &M {
         &self.master_khf
     }
 
@@ -166,19 +700,14 @@ where
     pub fn consolidate_khf(&mut self, objid: u64, mechanism: Consolidation) -> Result<(), Error> {
         self.load_khf(objid)?;
 
-        if let Some(entry) = self.mappings.get(&objid) {
+        if let Some(entry) = self.mappings.get_mut(&objid) {
+            entry.epoch = self.epoch;
+
             let mut curr_khf = self.object_khfs.get_mut(&entry.khf_id).unwrap();
             let mut next_khf = curr_khf.clone();
             let blocks = next_khf.consolidate(mechanism);
 
-            let mut io = BlockRecryptIo::<
-                <P as PersistentStorage>::Io<'_>,
-                Khf<R, H, E>,
-                Khf<R, H, E>,
-                C,
-                D,
-                E,
-            >::new(
+            let mut io = BlockRecryptIo::<<P as PersistentStorage>::Io<'_>, K, K, C, D, E>::new(
                 self.storage
                     .rw_handle(&entry.khf_id)
                     .map_err(|_| Error::Io)?,
@@ -204,166 +733,412 @@ where
         Ok(())
     }
 
-    /// Consolidates the master `Khf` using the specified `mechanism`.
-    pub fn consolidate_master_khf(&mut self, mechanism: Consolidation) {
-        self.master_khf.consolidate(mechanism);
+    /// Clones an object into a new object, `dst`, created with the same flags. The clone gets its
+    /// own `Khf`, with every block re-encrypted under fresh keys, so destroying either object only
+    /// forgets that object's keys.
+    pub fn clone_object(&mut self, src: u64, dst: u64) -> Result<(), Error> {
+        self.counted(|lethe| lethe.clone_to(src, dst))
     }
-}
 
-impl<S, P, A, R, C, H, const E: usize, const D: usize> PersistentStorage
-    for Lethe<S, P, A, R, C, H, E, D>
-where
-    S: Read + Write + Seek,
-    P: PersistentStorage<Id = u64>,
-    <P as PersistentStorage>::Flags: Default,
-    for<'a> <P as PersistentStorage>::Io<'a>: Read + Write + Seek,
-    for<'a> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'a>,
-    R: RngCore + CryptoRng + Clone + Default,
-    C: Crypter,
-    H: Hasher<E>,
-{
-    type Id = u64;
-    type Flags = <P as PersistentStorage>::Flags;
-    type Info = <P as PersistentStorage>::Info;
-    type Error = Error;
-    type Io<'a> = BlockCryptIo<'a, P::Io<'a>, Khf<R, H, E>, C, D, E>
-        where
-            S: 'a,
-            P: 'a,
-            A: 'a,
-            R: 'a,
-            H: 'a,
-            C: 'a;
+    fn clone_to(&mut self, src: u64, dst: u64) -> Result<(), Error> {
+        self.load_khf(src)?;
+        self.load_metadata(src)?;
 
-    fn create(&mut self, objid: &Self::Id, flags: &Self::Flags) -> Result<(), Self::Error> {
-        let map_id = self.allocator.alloc().map_err(|_| Error::Alloc)?;
-        let khf_id = self.allocator.alloc().map_err(|_| Error::Alloc)?;
+        let entry = self.mappings.get(&src).ok_or(Error::MissingKhf)?;
+        let (from, src_khf_id, fanouts) = (entry.map_id, entry.khf_id, entry.fanouts.clone());
+        let metadata = self.metadata[&entry.meta_id].clone();
 
-        self.mappings.insert(*objid, MapEntry { map_id, khf_id });
+        let mut options = ObjectOptions::new();
+        options.khf_fanouts(&fanouts);
+        self.create_object(dst, &metadata.flags, &options)?;
 
-        self.object_khfs
-            .insert(khf_id, Khf::new(&self.object_khf_fanouts, R::default()));
-        self.master_khf.update(khf_id)?;
+        let entry = self.mappings.get(&dst).ok_or(Error::MissingKhf)?;
+        let (map_id, khf_id) = (entry.map_id, entry.khf_id);
 
-        self.storage
-            .create(&khf_id, &<P as PersistentStorage>::Flags::default())
-            .map_err(|_| Error::Io)?;
-        self.storage
-            .create(&map_id, &flags)
-            .map_err(|_| Error::Io)?;
+        // The clone expires along with the original.
+        if let Some(when) = metadata.expiry {
+            self.expiries.insert(entry.meta_id, when);
+        }
+        self.metadata.insert(entry.meta_id, metadata);
 
-        Ok(())
+        // The source's padding is copied along with its data, replacing any from creation.
+        self.storage.truncate(&map_id, 0).map_err(|_| Error::Io)?;
+
+        let mut buf = vec![0; COPY_CHUNK_BLOCKS * D];
+        let res = self.recrypt_into(from, src_khf_id, map_id, khf_id, &mut buf);
+        buf.zeroize();
+        res
     }
 
-    fn destroy(&mut self, objid: &Self::Id) -> Result<(), Self::Error> {
-        if let Some(entry) = self.mappings.remove(objid) {
-            self.allocator
-                .dealloc(entry.map_id)
-                .map_err(|_| Error::Dealloc)?;
-            self.allocator
-                .dealloc(entry.khf_id)
-                .map_err(|_| Error::Dealloc)?;
+    // Copies the underlying object `from` into `to`, decrypting each chunk under the `Khf`
+    // `from_khf_id` and encrypting it straight back under `to_khf_id`, so every block is read and
+    // written once. The plaintext passes through `buf`.
+    fn recrypt_into(
+        &mut self,
+        from: u64,
+        from_khf_id: u64,
+        to: u64,
+        to_khf_id: u64,
+        buf: &mut [u8],
+    ) -> Result<(), Error> {
+        let len = self.raw_len(from)?;
+        let mut offset = 0;
+
+        while offset < len {
+            let n = (len - offset).min(buf.len() as u64) as usize;
+
+            let mut io = BlockCryptIo::<<P as PersistentStorage>::Io<'_>, K, C, D, E>::new(
+                self.storage.read_handle(&from).map_err(|_| Error::Io)?,
+                self.object_khfs
+                    .get_mut(&from_khf_id)
+                    .ok_or(Error::MissingKhf)?,
+            );
+            io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+            io.read_exact(&mut buf[..n]).map_err(|_| Error::Io)?;
 
-            self.object_khfs.remove(&entry.khf_id);
-            self.master_khf.update(entry.khf_id)?;
+            let mut io = BlockCryptIo::<<P as PersistentStorage>::Io<'_>, K, C, D, E>::new(
+                self.storage.write_handle(&to).map_err(|_| Error::Io)?,
+                self.object_khfs
+                    .get_mut(&to_khf_id)
+                    .ok_or(Error::MissingKhf)?,
+            );
+            io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+            io.write_all(&buf[..n]).map_err(|_| Error::Io)?;
 
-            self.storage.destroy(&entry.khf_id).map_err(|_| Error::Io)?;
-            self.storage.destroy(&entry.map_id).map_err(|_| Error::Io)?;
+            offset += n as u64;
         }
+
         Ok(())
     }
 
-    fn get_info(&mut self, objid: &Self::Id) -> Result<Self::Info, Self::Error> {
-        self.storage.get_info(objid).map_err(|_| Error::Io)
+    /// Renames an object from `old` to `new` by repointing its mapping, without moving any data.
+    /// If `new` already exists, it's securely destroyed if `replace` is set, and the rename fails
+    /// with `Error::ObjectExists` otherwise.
+    pub fn rename(&mut self, old: u64, new: u64, replace: bool) -> Result<(), Error> {
+        if !self.mappings.contains_key(&old) {
+            return Err(Error::MissingKhf);
+        }
+
+        if old == new {
+            return Ok(());
+        }
+
+        if self.mappings.contains_key(&new) && !replace {
+            return Err(Error::ObjectExists);
+        }
+
+        // An object already at `new` is destroyed as part of the rename.
+        self.counted(|lethe| {
+            lethe.remove_object(new)?;
+            let entry = lethe.mappings.remove(&old).unwrap();
+            lethe.mappings.insert(new, entry);
+            Ok(())
+        })
     }
 
-    fn set_info(&mut self, objid: &Self::Id, info: Self::Info) -> Result<(), Self::Error> {
-        self.storage.set_info(objid, info).map_err(|_| Error::Io)
+    /// Exchanges the mappings of two objects, so each takes the other's ID.
+    pub fn exchange(&mut self, a: u64, b: u64) -> Result<(), Error> {
+        if !self.mappings.contains_key(&a) || !self.mappings.contains_key(&b) {
+            return Err(Error::MissingKhf);
+        }
+
+        if a == b {
+            return Ok(());
+        }
+
+        self.counted(|lethe| {
+            let entry_a = lethe.mappings.remove(&a).unwrap();
+            let entry_b = lethe.mappings.remove(&b).unwrap();
+            lethe.mappings.insert(a, entry_b);
+            lethe.mappings.insert(b, entry_a);
+            Ok(())
+        })
     }
 
-    fn read_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
-        self.load_khf(*objid)?;
-        let entry = self.mappings.get(objid).ok_or(Error::MissingKhf)?;
-        let khf = self
-            .object_khfs
-            .get_mut(&entry.khf_id)
-            .ok_or(Error::MissingKhf)?;
-        let io = self
-            .storage
-            .read_handle(&entry.map_id)
-            .map_err(|_| Error::Io)?;
-        Ok(BlockCryptIo::new(io, khf))
+    /// Restores an object from the trash under its original ID.
+    pub fn restore(&mut self, objid: u64) -> Result<(), Error> {
+        if self.mappings.contains_key(&objid) {
+            return Err(Error::ObjectExists);
+        }
+
+        let trashed = self.trash.remove(&objid).ok_or(Error::MissingTrash)?;
+        self.counted(|lethe| {
+            lethe.mappings.insert(objid, trashed.entry);
+            Ok(())
+        })
     }
 
-    fn write_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
-        self.load_khf(*objid)?;
+    /// Returns the IDs of the objects in the trash.
+    pub fn trashed(&self) -> Vec<u64> {
+        self.trash.keys().copied().collect()
+    }
 
-        let entry = self.mappings.get(objid).ok_or(Error::MissingKhf)?;
-        let khf = self
-            .object_khfs
-            .get_mut(&entry.khf_id)
-            .ok_or(Error::MissingKhf)?;
-        let io = self
-            .storage
-            .rw_handle(&entry.map_id)
-            .map_err(|_| Error::Io)?;
+    /// Securely deletes everything in the trash, regardless of grace periods, once committed.
+    pub fn empty_trash(&mut self) -> Result<(), Error> {
+        self.purge_trash(|_| true)
+    }
+
+    // Purges the trashed objects that `expired` selects. They're removed from the trash even if
+    // purging fails.
+    fn purge_trash(&mut self, expired: impl Fn(&Trashed) -> bool) -> Result<(), Error> {
+        let objids: Vec<u64> = self
+            .trash
+            .iter()
+            .filter(|(_, trashed)| expired(trashed))
+            .map(|(objid, _)| *objid)
+            .collect();
+
+        let mut res = Ok(());
+        for objid in objids {
+            let trashed = self.trash.remove(&objid).unwrap();
+            res = res.and(self.purge(trashed.entry));
+        }
+        res
+    }
+
+    // Unmaps an object, if it exists. In grace mode, destroyed objects are kept in the trash until
+    // their grace period expires.
+    fn remove_object(&mut self, objid: u64) -> Result<(), Error> {
+        if let Some(entry) = self.mappings.remove(&objid) {
+            if self.grace_period.is_none() {
+                self.purge(entry)?;
+            } else {
+                let trashed = Trashed {
+                    entry,
+                    epoch: self.epoch,
+                };
+                if let Some(older) = self.trash.insert(objid, trashed) {
+                    self.purge(older.entry)?;
+                }
+            }
+
+            self.pending.destroys += 1;
+        }
+        Ok(())
+    }
 
+    // Destroys an unmapped object for good.
+    fn purge(&mut self, entry: MapEntry) -> Result<(), Error> {
+        self.allocator
+            .dealloc(entry.map_id)
+            .map_err(|_| Error::Dealloc)?;
+        self.allocator
+            .dealloc(entry.khf_id)
+            .map_err(|_| Error::Dealloc)?;
+        self.allocator
+            .dealloc(entry.meta_id)
+            .map_err(|_| Error::Dealloc)?;
+
+        // Updating the keys securely deletes the `Khf` and metadata once committed.
+        self.object_khfs.remove(&entry.khf_id);
         self.master_khf.update(entry.khf_id)?;
+        self.metadata.remove(&entry.meta_id);
+        self.master_khf.update(entry.meta_id)?;
+        self.expiries.remove(&entry.meta_id);
+        self.pending.forgotten = true;
 
-        Ok(BlockCryptIo::new(io, khf))
+        self.storage.destroy(&entry.khf_id).map_err(|_| Error::Io)?;
+        self.storage
+            .destroy(&entry.meta_id)
+            .map_err(|_| Error::Io)?;
+        self.storage.destroy(&entry.map_id).map_err(|_| Error::Io)
     }
 
-    fn rw_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
-        self.write_handle(objid)
+    /// Sets when an object expires, or clears its expiry with `None`. Expired objects are
+    /// destroyed at the first commit after their deadline, bypassing the trash.
+    pub fn set_expiry(&mut self, objid: u64, when: Option<SystemTime>) -> Result<(), Error> {
+        self.load_metadata(objid)?;
+        let meta_id = self.mappings.get(&objid).ok_or(Error::MissingKhf)?.meta_id;
+
+        self.counted(|lethe| {
+            lethe.metadata.get_mut(&meta_id).unwrap().expiry = when;
+
+            // The metadata is rewritten under a fresh key at the next commit.
+            lethe.master_khf.update(meta_id)?;
+
+            match when {
+                Some(when) => lethe.expiries.insert(meta_id, when),
+                None => lethe.expiries.remove(&meta_id),
+            };
+            Ok(())
+        })
     }
 
-    fn truncate(&mut self, objid: &Self::Id, size: u64) -> Result<(), Self::Error> {
-        // Number of bytes past a block.
-        let extra = size % D as u64;
+    /// Returns when an object expires, if ever.
+    pub fn expiry(&mut self, objid: u64) -> Result<Option<SystemTime>, Error> {
+        self.load_metadata(objid)?;
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+        Ok(self.metadata[&entry.meta_id].expiry)
+    }
 
-        // Need to rewrite the extra bytes.
-        if extra > 0 {
-            let mut io = self.rw_handle(objid)?;
-            let mut buf = vec![0; extra as usize];
-            let offset = (size / D as u64) * D as u64;
+    /// Destroys every object, live or trashed, whose deadline has passed, returning their IDs.
+    /// Their keys are forgotten at the next commit. This also runs as part of every commit.
+    pub fn sweep_expired(&mut self) -> Result<Vec<u64>, Error> {
+        let now = SystemTime::now();
+        let expired: Vec<u64> = self
+            .expiries
+            .iter()
+            .filter(|(_, when)| **when <= now)
+            .map(|(meta_id, _)| *meta_id)
+            .collect();
+
+        let mut objids = vec![];
+        for meta_id in expired {
+            let live = self
+                .mappings
+                .iter()
+                .find(|(_, entry)| entry.meta_id == meta_id)
+                .map(|(objid, _)| *objid);
+
+            let (objid, entry) = if let Some(objid) = live {
+                (objid, self.mappings.remove(&objid).unwrap())
+            } else if let Some(objid) = self
+                .trash
+                .iter()
+                .find(|(_, trashed)| trashed.entry.meta_id == meta_id)
+                .map(|(objid, _)| *objid)
+            {
+                (objid, self.trash.remove(&objid).unwrap().entry)
+            } else {
+                self.expiries.remove(&meta_id);
+                continue;
+            };
+
+            self.purge(entry)?;
+            objids.push(objid);
+        }
 
-            // Read in the extra bytes.
-            io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
-            io.read(&mut buf).map_err(|_| Error::Io)?;
+        Ok(objids)
+    }
 
-            // Write the extra bytes.
-            io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
-            io.write(&buf).map_err(|_| Error::Io)?;
+    /// Returns how long it's been since the last commit (or since the store was opened, if it
+    /// hasn't committed since).
+    pub fn since_last_commit(&self) -> Duration {
+        self.last_commit.elapsed()
+    }
+
+    // Whether anything has changed since the last commit.
+    pub(crate) fn uncommitted(&self) -> bool {
+        self.pending.ops > 0 || self.pending.forgotten
+    }
+
+    /// Commits if any of the auto-persist thresholds set on `LetheBuilder` has been reached,
+    /// returning whether it did. This is checked at each operation, but applications that set
+    /// an interval should also call it periodically.
+    pub fn persist_if_due(&mut self) -> Result<bool, Error> {
+        let reached = |limit: Option<u64>, count: u64| limit.is_some_and(|limit| count >= limit);
+
+        let due = reached(self.auto_persist.ops, self.pending.ops)
+            || reached(self.auto_persist.bytes, self.pending.bytes)
+            || reached(self.auto_persist.destroys, self.pending.destroys)
+            || self
+                .auto_persist
+                .interval
+                .is_some_and(|interval| self.last_commit.elapsed() >= interval);
+
+        if !due || self.auto_persist_suspended {
+            return Ok(false);
         }
 
-        // Truncate the forest. Not needed for security, but nice for efficiency.
-        let keys = (size + (D as u64 - 1)) / D as u64;
-        self.get_khf_mut(*objid)?
-            .ok_or(Error::MissingKhf)?
-            .truncate(keys);
+        self.persist_state()?;
+        Ok(true)
+    }
+
+    /// Suspends (or resumes) auto-persisting, returning whether it was already suspended.
+    pub(crate) fn suspend_auto_persist(&mut self, suspend: bool) -> bool {
+        std::mem::replace(&mut self.auto_persist_suspended, suspend)
+    }
 
-        // Truncate the object itself.
-        self.storage.truncate(objid, size).map_err(|_| Error::Io)
+    // Runs a compound operation without committing partway through it.
+    fn without_auto_persist<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let suspended = self.suspend_auto_persist(true);
+        let res = f(self);
+        self.suspend_auto_persist(suspended);
+        res
     }
 
-    fn persist_state(&mut self) -> Result<(), Self::Error> {
-        // Persist the updated object `Khf`s.
-        for khf_id in self.master_khf.commit() {
-            let khf = self.object_khfs.get_mut(&khf_id).unwrap();
-            khf.commit();
+    // Runs a public operation, counting it once towards the auto-persist thresholds. Whether a
+    // commit is due is only checked once it has finished. It's counted even if it fails, since
+    // it may have changed things before failing.
+    fn counted<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        let res = self.without_auto_persist(f);
+        self.pending.ops += 1;
+        let res = res?;
+        self.persist_if_due()?;
+        Ok(res)
+    }
 
-            let ser = bincode::serialize(khf)?;
-            let key = self.master_khf.derive(khf_id)?;
+    /// Makes pending deletions effective right away. Once this returns, destroyed objects and
+    /// truncated and shredded data are unrecoverable, and the new root is durable in the
+    /// enclave. If `empty_trash` is set, everything in the trash is destroyed along with them,
+    /// regardless of grace periods; otherwise the trash is left alone.
+    ///
+    /// This commits everything changed since the last commit, just like `persist_state()`, except
+    /// that expired objects aren't swept, trashed objects don't age, and the epoch doesn't
+    /// advance. Nothing is committed if nothing has changed since the last commit.
+    pub fn forget_barrier(&mut self, empty_trash: bool) -> Result<(), Error> {
+        if empty_trash {
+            self.empty_trash()?;
+        }
+
+        if !self.uncommitted() {
+            return Ok(());
+        }
+
+        self.rotate_master_key()
+    }
+
+    // Commits everything updated since the last commit, moving on to the next epoch.
+    fn commit(&mut self) -> Result<(), Error> {
+        self.rotate_master_key()?;
+
+        // Everything from here on belongs to the next epoch.
+        self.epoch += 1;
+
+        Ok(())
+    }
+
+    // Persists everything updated since the last commit under a fresh master key, then retires
+    // the previous one.
+    fn rotate_master_key(&mut self) -> Result<(), Error> {
+        // The counter is only incremented once everything else is durable.
+        let counter = self
+            .enclave
+            .counter()
+            .map_err(|_| Error::Enclave)?
+            .checked_add(1)
+            .ok_or(Error::Enclave)?;
+
+        // Persist the updated object `Khf`s and metadata.
+        for id in self.master_khf.commit() {
+            // Updated keys belong to either an object `Khf` or object metadata. Destroyed objects
+            // no longer have either to persist.
+            let mut ser = if let Some(khf) = self.object_khfs.get_mut(&id) {
+                khf.commit();
+                bincode::serialize(khf)?
+            } else if let Some(metadata) = self.metadata.get(&id) {
+                bincode::serialize(metadata)?
+            } else {
+                continue;
+            };
+
+            let mut key = self.master_khf.derive(id)?;
             let mut io = CryptIo::<<P as PersistentStorage>::Io<'_>, C, E>::new(
-                self.storage.write_handle(&khf_id).map_err(|_| Error::Io)?,
+                self.storage.write_handle(&id).map_err(|_| Error::Io)?,
                 key,
             );
+            key.zeroize();
 
-            io.write_all(&ser).map_err(|_| Error::Io)?;
+            let res = io.write_all(&ser).map_err(|_| Error::Io);
+            ser.zeroize();
+            res?;
         }
 
         // Generate new master key.
-        R::default().fill_bytes(&mut self.master_key);
+        R::default().fill_bytes(&mut *self.master_key);
 
         // Persist the master `Khf`.
         {
@@ -371,10 +1146,12 @@ where
                 self.storage
                     .write_handle(&MASTER_KHF_OBJID)
                     .map_err(|_| Error::Io)?,
-                self.master_key,
+                *self.master_key,
             );
-            let ser = bincode::serialize(&self.master_khf)?;
-            io.write_all(&ser).map_err(|_| Error::Io)?;
+            let mut ser = bincode::serialize(&self.master_khf)?;
+            let res = io.write_all(&ser).map_err(|_| Error::Io);
+            ser.zeroize();
+            res?;
         }
 
         // Persist the object `Khf` fanouts.
@@ -383,7 +1160,7 @@ where
                 self.storage
                     .write_handle(&OBJECT_KHF_FANOUTS_OBJID)
                     .map_err(|_| Error::Io)?,
-                self.master_key,
+                *self.master_key,
             );
             let ser = bincode::serialize(&self.object_khf_fanouts)?;
             io.write_all(&ser).map_err(|_| Error::Io)?;
@@ -395,7 +1172,7 @@ where
                 self.storage
                     .write_handle(&ALLOCATOR_OBJID)
                     .map_err(|_| Error::Io)?,
-                self.master_key,
+                *self.master_key,
             );
             let ser = bincode::serialize(&self.allocator)?;
             io.write_all(&ser).map_err(|_| Error::Io)?;
@@ -407,36 +1184,332 @@ where
                 self.storage
                     .write_handle(&MAPPINGS_OBJID)
                     .map_err(|_| Error::Io)?,
-                self.master_key,
+                *self.master_key,
             );
             let ser = bincode::serialize(&self.mappings)?;
             io.write_all(&ser).map_err(|_| Error::Io)?;
         }
 
-        // Persist the master key.
+        // Persist the miscellaneous state.
+        {
+            let mut io = CryptIo::<<P as PersistentStorage>::Io<'_>, C, E>::new(
+                self.storage
+                    .write_handle(&STATE_OBJID)
+                    .map_err(|_| Error::Io)?,
+                *self.master_key,
+            );
+            let ser = bincode::serialize(&State {
+                magic: STATE_MAGIC,
+                epoch: self.epoch,
+                counter,
+                snapshots: self
+                    .snapshots
+                    .values()
+                    .flat_map(|snapshot| snapshot.objects.values())
+                    .map(|object| object.map_id)
+                    .collect(),
+                trash: self.trash.clone(),
+                expiries: self.expiries.clone(),
+                padding: self.padding,
+            })?;
+            io.write_all(&ser).map_err(|_| Error::Io)?;
+        }
+
+        // Persist the master key. The enclave keeps the previous one around until the state
+        // protected by the new one is durable.
         self.enclave
-            .seek(SeekFrom::Start(0))
-            .map_err(|_| Error::Io)?;
+            .seal_root(&self.master_key[..])
+            .map_err(|_| Error::Enclave)?;
+
+        // Persist state of the underlying storage, and make sure the new root is durable before
+        // the previous one is gone. Until then, the previous root is the one to fall back on, so
+        // the new one is discarded on failure. Otherwise, it'd be the newest root when retrying,
+        // and the retry would seal over the previous one.
+        let res = self
+            .storage
+            .persist_state()
+            .map_err(|_| Error::Io)
+            .and_then(|()| self.enclave.sync().map_err(|_| Error::Enclave));
+        if let Err(err) = res {
+            let _ = self.enclave.discard_root();
+            return Err(err);
+        }
+
+        // Securely erase the previous master key, for good once the erasure is durable too.
+        self.enclave.retire_root().map_err(|_| Error::Enclave)?;
+        self.enclave.sync().map_err(|_| Error::Enclave)?;
+
+        // Bump the counter, marking the committed state as the latest.
         self.enclave
-            .write_all(&self.master_key)
+            .increment_counter()
+            .map_err(|_| Error::Enclave)?;
+        self.enclave.sync().map_err(|_| Error::Enclave)?;
+
+        self.pending = Pending::default();
+        self.last_commit = Instant::now();
+
+        Ok(())
+    }
+
+    /// Consolidates the master `Khf` using the specified `mechanism`.
+    pub fn consolidate_master_khf(&mut self, mechanism: Consolidation) {
+        self.master_khf.consolidate(mechanism);
+    }
+
+    /// Starts a transaction, which stages operations across objects and commits them together.
+    pub fn transaction(&mut self) -> Transaction<'_, S, P, A, R, C, H, E, D, K, M> {
+        Transaction::new(self)
+    }
+
+    /// Returns whether an object exists. This only consults the mappings, so neither the object's
+    /// `Khf` nor its metadata are loaded.
+    pub fn exists(&self, objid: u64) -> bool {
+        self.mappings.contains_key(&objid)
+    }
+
+    /// Lists every object in ID order.
+    pub fn objects(&mut self) -> Result<Vec<ObjectEntry>, Error> {
+        self.iter_objects(..).collect()
+    }
+
+    /// Iterates over the objects with IDs in `range`, in ID order. Sizes come from each object's
+    /// metadata, which is read as the iterator advances but not cached, so iterating over a large
+    /// store doesn't pin all of its metadata in memory. Object `Khf`s are never loaded.
+    pub fn iter_objects<'a>(
+        &'a mut self,
+        range: impl RangeBounds<u64> + 'a,
+    ) -> impl Iterator<Item = Result<ObjectEntry, Error>> + 'a {
+        let objids: Vec<u64> = self
+            .mappings
+            .range(range)
+            .map(|(objid, _)| *objid)
+            .collect();
+        objids
+            .into_iter()
+            .map(move |objid| self.object_entry(objid))
+    }
+
+    fn object_entry(&mut self, objid: u64) -> Result<ObjectEntry, Error> {
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+        let (map_id, khf_id, meta_id) = (entry.map_id, entry.khf_id, entry.meta_id);
+
+        let size = match self.metadata.get(&meta_id) {
+            Some(metadata) => metadata.len,
+            None => self.read_metadata(meta_id)?.len,
+        };
+
+        Ok(ObjectEntry {
+            objid,
+            map_id,
+            khf_id,
+            meta_id,
+            size,
+        })
+    }
+
+    /// Returns the current (uncommitted) epoch.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns store-wide statistics.
+    pub fn stats(&self) -> Result<LetheStats, Error> {
+        Ok(LetheStats {
+            epoch: self.epoch,
+            objects: self.mappings.len() as u64,
+            master_khf_keys: self.master_khf.fragmentation(),
+            master_khf_size: bincode::serialized_size(&self.master_khf)?,
+            cached_khfs: self.object_khfs.len() as u64,
+            cached_metadata: self.metadata.len() as u64,
+        })
+    }
+
+    /// Returns statistics for an object, loading its `Khf` if it isn't cached.
+    pub fn object_stats(&mut self, objid: u64) -> Result<ObjectStats, Error> {
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+        let cached = self.object_khfs.contains_key(&entry.khf_id);
+
+        // The logical length determines the number of blocks, not the padded one.
+        let len = self.object_entry(objid)?.size;
+
+        self.load_khf(objid)?;
+
+        let entry = self.mappings.get(&objid).ok_or(Error::MissingKhf)?;
+        let khf = self
+            .object_khfs
+            .get(&entry.khf_id)
+            .ok_or(Error::MissingKhf)?;
+
+        Ok(ObjectStats {
+            blocks: (len + (D as u64 - 1)) / D as u64,
+            khf_keys: khf.fragmentation(),
+            khf_depth: entry.fanouts.len() as u64,
+            khf_size: bincode::serialized_size(khf)?,
+            epoch: entry.epoch,
+            cached,
+        })
+    }
+}
+
+impl<S, P, A, R, C, H, const E: usize, const D: usize, K, M> PersistentStorage
+    for Lethe<S, P, A, R, C, H, E, D, K, M>
+where
+    S: Enclave,
+    P: PersistentStorage<Id = u64>,
+    for<'a> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Io<'a>: Read + Write + Seek,
+    for<'a> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'a>,
+    R: RngCore + CryptoRng + Clone + Default,
+    C: Crypter,
+    H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
+{
+    type Id = u64;
+    type Flags = <P as PersistentStorage>::Flags;
+    type Info = <P as PersistentStorage>::Info;
+    type Error = Error;
+    type Io<'a>
+        = ObjectIo<'a, BlockCryptIo<'a, P::Io<'a>, K, C, D, E>, D>
+    where
+        S: 'a,
+        P: 'a,
+        A: 'a,
+        R: 'a,
+        H: 'a,
+        C: 'a,
+        K: 'a,
+        M: 'a;
+
+    fn create(&mut self, objid: &Self::Id, flags: &Self::Flags) -> Result<(), Self::Error> {
+        self.create_with_options(*objid, flags, &ObjectOptions::default())
+    }
+
+    fn destroy(&mut self, objid: &Self::Id) -> Result<(), Self::Error> {
+        if self.mappings.contains_key(objid) {
+            self.counted(|lethe| lethe.remove_object(*objid))?;
+        }
+        Ok(())
+    }
+
+    fn get_info(&mut self, objid: &Self::Id) -> Result<Self::Info, Self::Error> {
+        self.load_metadata(*objid)?;
+        let entry = self.mappings.get(objid).ok_or(Error::MissingKhf)?;
+        Ok(self.metadata[&entry.meta_id].info.clone())
+    }
+
+    fn set_info(&mut self, objid: &Self::Id, info: Self::Info) -> Result<(), Self::Error> {
+        self.load_metadata(*objid)?;
+        self.counted(|lethe| {
+            let entry = lethe.mappings.get(objid).ok_or(Error::MissingKhf)?;
+            lethe.metadata.get_mut(&entry.meta_id).unwrap().info = info;
+
+            // The metadata is rewritten under a fresh key at the next commit.
+            lethe.master_khf.update(entry.meta_id)?;
+            Ok(())
+        })
+    }
+
+    fn read_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        self.load_khf(*objid)?;
+        self.load_metadata(*objid)?;
+
+        let entry = self.mappings.get(objid).ok_or(Error::MissingKhf)?;
+        let khf = self
+            .object_khfs
+            .get_mut(&entry.khf_id)
+            .ok_or(Error::MissingKhf)?;
+        let metadata = self
+            .metadata
+            .get_mut(&entry.meta_id)
+            .ok_or(Error::MissingKhf)?;
+        let io = self
+            .storage
+            .read_handle(&entry.map_id)
             .map_err(|_| Error::Io)?;
 
-        // Persist state of the underlying storage.
-        self.storage.persist_state().map_err(|_| Error::Io)
+        Ok(ObjectIo::new(
+            BlockCryptIo::new(io, khf),
+            &mut metadata.len,
+            &mut metadata.holes,
+            self.padding,
+        ))
+    }
+
+    fn write_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        // Opening the handle counts as the operation. The bytes written through it are counted
+        // as they're written, and checked against the thresholds by the next operation.
+        self.pending.ops += 1;
+        self.persist_if_due()?;
+        self.open_object(*objid)
+    }
+
+    fn rw_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        self.write_handle(objid)
+    }
+
+    fn truncate(&mut self, objid: &Self::Id, size: u64) -> Result<(), Self::Error> {
+        self.counted(|lethe| lethe.truncate_object(*objid, size))
+    }
+
+    // The underlying storage is persisted last, after all of our state has been written to it.
+    // This lets `Lethe` instances be stacked: an outer commit writes its state into the inner
+    // instance, whose own commit then makes everything durable at once.
+    fn persist_state(&mut self) -> Result<(), Self::Error> {
+        // Purge trashed objects whose grace period has run out, or all of them if grace mode was
+        // turned off.
+        let (epoch, grace_period) = (self.epoch, self.grace_period);
+        self.purge_trash(|trashed| match grace_period {
+            Some(grace_period) => trashed.epoch + grace_period <= epoch,
+            None => true,
+        })?;
+
+        // Destroy expired objects, so their keys are gone once this commit is durable.
+        self.sweep_expired()?;
+
+        self.commit()
     }
 
+    // Mirrors `persist_state()`: the underlying storage is loaded first, so a stacked inner
+    // instance is usable by the time we read our own state out of it.
     fn load_state(&mut self) -> Result<(), Self::Error> {
         // Load state of the underlying storage.
         self.storage.load_state().map_err(|_| Error::Io)?;
 
-        // Load the master key.
-        let mut master_key = [0; E];
-        self.enclave
-            .seek(SeekFrom::Start(0))
-            .map_err(|_| Error::Io)?;
-        self.enclave
-            .read_exact(&mut master_key)
-            .map_err(|_| Error::Io)?;
+        // Load the master key. The newest root may be from an interrupted commit, so we pick the
+        // newest one that actually decrypts the committed state.
+        let mut found = None;
+        let roots = Zeroizing::new(self.enclave.unseal_roots().map_err(|_| Error::Enclave)?);
+        for (stale, root) in roots.iter().enumerate() {
+            if root.len() != E {
+                continue;
+            }
+
+            // Zeroized however we leave, including on errors.
+            let mut master_key = Zeroizing::new([0; E]);
+            master_key.copy_from_slice(root);
+
+            let mut io = CryptIo::<<P as PersistentStorage>::Io<'_>, C, E>::new(
+                self.storage
+                    .read_handle(&STATE_OBJID)
+                    .map_err(|_| Error::Io)?,
+                *master_key,
+            );
+            let mut ser = vec![];
+            io.read_to_end(&mut ser).map_err(|_| Error::Io)?;
+
+            let state = bincode::deserialize::<State>(&ser)
+                .ok()
+                .filter(|state| state.magic == STATE_MAGIC);
+            if let Some(state) = state {
+                found = Some((stale, master_key, state));
+                break;
+            }
+        }
+        drop(roots);
+        let (stale, master_key, state) = found.ok_or(Error::Enclave)?;
 
         // Load the master `Khf`.
         let master_khf = {
@@ -444,11 +1517,13 @@ where
                 self.storage
                     .read_handle(&MASTER_KHF_OBJID)
                     .map_err(|_| Error::Io)?,
-                master_key,
+                *master_key,
             );
             let mut ser = vec![];
             io.read_to_end(&mut ser).map_err(|_| Error::Io)?;
-            bincode::deserialize(&ser)?
+            let master_khf = bincode::deserialize(&ser);
+            ser.zeroize();
+            master_khf?
         };
 
         // Load the object `Khf` fanouts.
@@ -457,7 +1532,7 @@ where
                 self.storage
                     .read_handle(&OBJECT_KHF_FANOUTS_OBJID)
                     .map_err(|_| Error::Io)?,
-                master_key,
+                *master_key,
             );
             let mut ser = vec![];
             io.read_to_end(&mut ser).map_err(|_| Error::Io)?;
@@ -470,7 +1545,7 @@ where
                 self.storage
                     .read_handle(&ALLOCATOR_OBJID)
                     .map_err(|_| Error::Io)?,
-                master_key,
+                *master_key,
             );
             let mut ser = vec![];
             io.read_to_end(&mut ser).map_err(|_| Error::Io)?;
@@ -483,61 +1558,125 @@ where
                 self.storage
                     .read_handle(&MAPPINGS_OBJID)
                     .map_err(|_| Error::Io)?,
-                master_key,
+                *master_key,
             );
             let mut ser = vec![];
             io.read_to_end(&mut ser).map_err(|_| Error::Io)?;
             bincode::deserialize(&ser)?
         };
 
+        // The committed state must be at least as new as the enclave's counter says. It can be
+        // one ahead if we crashed before incrementing the counter, in which case we catch up.
+        let counter = self.enclave.counter().map_err(|_| Error::Enclave)?;
+        if state.counter == counter + 1 {
+            self.enclave
+                .increment_counter()
+                .map_err(|_| Error::Enclave)?;
+        } else if state.counter != counter {
+            return Err(Error::Rollback);
+        }
+
+        // Securely erase any roots from interrupted commits.
+        for _ in 0..stale {
+            self.enclave.discard_root().map_err(|_| Error::Enclave)?;
+        }
+
         // Update state after all the fallible operations.
-        self.master_key = master_key;
+        *self.master_key = *master_key;
         self.master_khf = master_khf;
         self.object_khf_fanouts = object_khf_fanouts;
         self.allocator = allocator;
         self.mappings = mappings;
+        self.trash = state.trash;
+        self.expiries = state.expiries;
+        self.padding = state.padding;
+        self.pending = Pending::default();
+        self.last_commit = Instant::now();
+        self.epoch = state.epoch + 1;
+
+        // Anything cached may have uncommitted changes.
+        self.object_khfs.clear();
+        self.metadata.clear();
+
+        // Destroy the copies left behind by snapshots. They may already be gone if they were
+        // released after the state was committed.
+        self.snapshots.clear();
+        for map_id in state.snapshots {
+            let _ = self.storage.destroy(&map_id);
+            let _ = self.allocator.dealloc(map_id);
+        }
 
         Ok(())
     }
 }
 
-impl<S, P, A, R, C, H, const E: usize, const D: usize> Drop for Lethe<S, P, A, R, C, H, E, D>
+impl<S, P, A, R, C, H, const E: usize, const D: usize, K, M> Drop
+    for Lethe<S, P, A, R, C, H, E, D, K, M>
 where
-    S: Read + Write + Seek,
+    S: Enclave,
     P: PersistentStorage<Id = u64>,
-    <P as PersistentStorage>::Flags: Default,
+    for<'a> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'a>,
     for<'a> <P as PersistentStorage>::Io<'a>: Read + Write + Seek,
     for<'a> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'a>,
     R: RngCore + CryptoRng + Clone + Default,
     C: Crypter,
     H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
 {
     fn drop(&mut self) {
+        // The master key zeroizes itself.
         self.persist_state().unwrap();
     }
 }
 
-pub struct LetheBuilder<S, P, A, R, C, H, const E: usize, const D: usize> {
+pub struct LetheBuilder<
+    S,
+    P,
+    A,
+    R,
+    C,
+    H,
+    const E: usize,
+    const D: usize,
+    K = Khf<R, H, E>,
+    M = Khf<R, H, E>,
+> {
     master_khf_fanouts: Vec<u64>,
     object_khf_fanouts: Vec<u64>,
-    pd: PhantomData<(S, P, A, R, C, H)>,
+    padding: PaddingPolicy,
+    lock_memory: bool,
+    grace_period: Option<u64>,
+    auto_persist: AutoPersist,
+    pd: PhantomData<(S, P, A, R, C, H, K, M)>,
 }
 
-impl<S, P, A, R, C, H, const E: usize, const D: usize> LetheBuilder<S, P, A, R, C, H, E, D>
+impl<S, P, A, R, C, H, const E: usize, const D: usize, K, M>
+    LetheBuilder<S, P, A, R, C, H, E, D, K, M>
 where
-    S: Read + Write + Seek,
+    S: Enclave,
     P: PersistentStorage<Id = u64>,
-    <P as PersistentStorage>::Flags: Default,
+    for<'a> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'a>,
     for<'a> <P as PersistentStorage>::Io<'a>: Read + Write + Seek,
     for<'a> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'a>,
     R: RngCore + CryptoRng + Clone + Default,
     C: Crypter,
     H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
 {
     pub fn new() -> Self {
         Self {
             master_khf_fanouts: DEFAULT_MASTER_KHF_FANOUTS.to_vec(),
             object_khf_fanouts: DEFAULT_OBJECT_KHF_FANOUTS.to_vec(),
+            padding: PaddingPolicy::default(),
+            lock_memory: false,
+            grace_period: None,
+            auto_persist: AutoPersist::default(),
             pd: PhantomData,
         }
     }
@@ -552,35 +1691,109 @@ where
         self
     }
 
-    pub fn build(&mut self, enclave: S, storage: P) -> Lethe<S, P, A, R, C, H, E, D> {
-        let mut master_key = [0; E];
-        R::default().fill_bytes(&mut master_key);
+    /// Sets how objects are padded in the underlying storage. The policy is saved with the store,
+    /// so this only matters for new stores: loading a store brings back the policy it was
+    /// committed with.
+    pub fn padding(&mut self, padding: PaddingPolicy) -> &mut Self {
+        self.padding = padding;
+        self
+    }
 
-        let mut lethe = Lethe {
+    /// Locks the page holding the master key in memory, so that it's never swapped out. This
+    /// may require raising the process's locked memory limit, without which `build()` fails
+    /// with `Error::MemoryLock`. Only the master key is covered: the keys held by the master and
+    /// object `Khf`s live in ordinary memory, and aren't zeroized when they're evicted.
+    pub fn lock_memory(&mut self, lock: bool) -> &mut Self {
+        self.lock_memory = lock;
+        self
+    }
+
+    /// Turns on grace mode, in which destroyed objects go into a trash from which they can be
+    /// restored. Trashed objects survive `epochs` commits before they're securely deleted, so
+    /// with `0` they're deleted at the next commit as usual.
+    pub fn grace_period(&mut self, epochs: u64) -> &mut Self {
+        self.grace_period = Some(epochs);
+        self
+    }
+
+    /// Commits automatically once `ops` creates, writes, truncates, destroys and `set_info()`s
+    /// have been made since the last commit.
+    pub fn persist_after_ops(&mut self, ops: u64) -> &mut Self {
+        self.auto_persist.ops = Some(ops);
+        self
+    }
+
+    /// Commits automatically once `bytes` have been written since the last commit. This is
+    /// checked after each `Lethe::write_at()`, but only at the next operation for writes through
+    /// a handle, which can overshoot it.
+    pub fn persist_after_bytes(&mut self, bytes: u64) -> &mut Self {
+        self.auto_persist.bytes = Some(bytes);
+        self
+    }
+
+    /// Commits automatically once `destroys` objects have been destroyed since the last commit.
+    pub fn persist_after_destroys(&mut self, destroys: u64) -> &mut Self {
+        self.auto_persist.destroys = Some(destroys);
+        self
+    }
+
+    /// Commits automatically once `interval` has passed since the last commit. Nothing runs in
+    /// the background, so this is only checked at operation boundaries and by
+    /// `Lethe::persist_if_due()`, which applications should call periodically.
+    pub fn persist_interval(&mut self, interval: Duration) -> &mut Self {
+        self.auto_persist.interval = Some(interval);
+        self
+    }
+
+    /// Builds a `Lethe` instance. This fails if the master key can't be locked in memory, or if
+    /// the reserved objects can't be created.
+    pub fn build(
+        &mut self,
+        enclave: S,
+        mut storage: P,
+    ) -> Result<Lethe<S, P, A, R, C, H, E, D, K, M>, Error> {
+        // The key is locked before it's generated so it never lands in swap.
+        let mut master_key = KeyPage::new();
+        if self.lock_memory {
+            master_key.lock()?;
+        }
+        R::default().fill_bytes(&mut *master_key);
+
+        // Reserved objects may already exist, e.g. if the storage is a `Lethe` instance that's
+        // been loaded, in which case they're left alone.
+        let mut allocator = A::default();
+        for &id in RESERVED_OBJIDS {
+            allocator.reserve(id).map_err(|_| Error::Alloc)?;
+            if storage.read_handle(&id).is_ok() {
+                continue;
+            }
+            storage
+                .create(&id, &<P as PersistentStorage>::Flags::default())
+                .map_err(|_| Error::Io)?;
+        }
+
+        Ok(Lethe {
             master_key,
-            master_khf: Khf::new(&self.master_khf_fanouts, R::default()),
+            master_khf: M::with_fanouts(&self.master_khf_fanouts),
             object_khfs: HashMap::new(),
+            metadata: HashMap::new(),
+            snapshots: HashMap::new(),
+            next_snapshot: 0,
+            padding: self.padding,
+            grace_period: self.grace_period,
+            trash: BTreeMap::new(),
+            expiries: BTreeMap::new(),
+            auto_persist: self.auto_persist,
+            auto_persist_suspended: false,
+            pending: Pending::default(),
+            last_commit: Instant::now(),
             object_khf_fanouts: self.object_khf_fanouts.clone(),
-            allocator: A::default(),
-            mappings: HashMap::new(),
+            allocator,
+            mappings: BTreeMap::new(),
+            epoch: 0,
             enclave,
             storage,
             pd: PhantomData,
-        };
-
-        for id in [
-            MASTER_KHF_OBJID,
-            OBJECT_KHF_FANOUTS_OBJID,
-            ALLOCATOR_OBJID,
-            MAPPINGS_OBJID,
-        ] {
-            lethe.allocator.reserve(id).unwrap();
-            lethe
-                .storage
-                .create(&id, &<P as PersistentStorage>::Flags::default())
-                .unwrap();
-        }
-
-        lethe
+        })
     }
 }
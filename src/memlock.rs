@@ -0,0 +1,140 @@
+//! Locking pages in memory so they're never swapped out.
+
+use crate::error::Error;
+use std::{
+    alloc::{self, Layout},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+use zeroize::Zeroize;
+
+/// Locks the pages spanned by `bytes` in memory.
+#[cfg(unix)]
+pub(crate) fn lock(bytes: &[u8]) -> Result<(), Error> {
+    // Safety: `mlock` only reads the address range, which is valid for `bytes.len()` bytes.
+    match unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } {
+        0 => Ok(()),
+        _ => Err(Error::MemoryLock),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock(_bytes: &[u8]) -> Result<(), Error> {
+    Err(Error::MemoryLock)
+}
+
+/// Unlocks the pages spanned by `bytes`. Pages that weren't locked are left alone.
+#[cfg(unix)]
+pub(crate) fn unlock(bytes: &[u8]) {
+    // Safety: see `lock()`.
+    unsafe {
+        libc::munlock(bytes.as_ptr().cast(), bytes.len());
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn unlock(_bytes: &[u8]) {}
+
+/// A key in a page-aligned allocation of its own, so that locking and unlocking it doesn't
+/// affect anything else. The key is zeroized, and its page unlocked, when it's dropped.
+pub(crate) struct KeyPage<const N: usize> {
+    ptr: NonNull<[u8; N]>,
+    layout: Layout,
+    locked: bool,
+}
+
+// Safety: `KeyPage` uniquely owns its allocation, just like a `Box`.
+unsafe impl<const N: usize> Send for KeyPage<N> {}
+unsafe impl<const N: usize> Sync for KeyPage<N> {}
+
+impl<const N: usize> KeyPage<N> {
+    /// Allocates a zeroed key.
+    pub(crate) fn new() -> Self {
+        let page = page_size();
+        let size = N.max(1).next_multiple_of(page);
+        let layout = Layout::from_size_align(size, page).unwrap();
+
+        // Safety: `layout` has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr.cast()).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+
+        Self {
+            ptr,
+            layout,
+            locked: false,
+        }
+    }
+
+    /// Locks the key's page in memory.
+    pub(crate) fn lock(&mut self) -> Result<(), Error> {
+        lock(self.page())?;
+        self.locked = true;
+        Ok(())
+    }
+
+    // The whole allocation, which spans exactly the pages holding the key.
+    fn page(&self) -> &[u8] {
+        // Safety: the allocation is valid for `self.layout.size()` bytes.
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr().cast(), self.layout.size()) }
+    }
+}
+
+impl<const N: usize> Deref for KeyPage<N> {
+    type Target = [u8; N];
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: the allocation is at least `N` bytes, initialized, and aligned.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<const N: usize> DerefMut for KeyPage<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: see `deref()`, and we have unique access.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<const N: usize> Drop for KeyPage<N> {
+    fn drop(&mut self) {
+        (**self).zeroize();
+        if self.locked {
+            unlock(self.page());
+        }
+
+        // Safety: the allocation was made with `self.layout`.
+        unsafe { alloc::dealloc(self.ptr.as_ptr().cast(), self.layout) }
+    }
+}
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    // Safety: `sysconf` has no preconditions.
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> usize {
+    4096
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_page() {
+        let mut key = KeyPage::<32>::new();
+        assert_eq!(*key, [0; 32]);
+        assert_eq!(key.ptr.as_ptr() as usize % page_size(), 0);
+        assert_eq!(key.page().len(), page_size());
+
+        key.copy_from_slice(&[7; 32]);
+        key.lock().unwrap();
+        assert_eq!(*key, [7; 32]);
+    }
+}
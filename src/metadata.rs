@@ -0,0 +1,21 @@
+use crate::io::Holes;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// An object's metadata. This is kept in its own storage object, encrypted under a key from the
+/// master `Khf`, so none of it is exposed to the underlying storage.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Metadata<I, F> {
+    /// The object's `Info`, as set by `set_info()`.
+    pub(crate) info: I,
+    /// The flags the object was created with, so that copies of it can be created alike.
+    pub(crate) flags: F,
+    /// The object's logical length, which may be shorter than the underlying object if it's
+    /// padded.
+    pub(crate) len: u64,
+    /// Blocks that have been shredded or never written, which read back as zeros until they're
+    /// written to.
+    pub(crate) holes: Holes,
+    /// When the object is securely forgotten, if ever.
+    pub(crate) expiry: Option<SystemTime>,
+}
@@ -0,0 +1,338 @@
+use crate::{enclave::Enclave, error::Error, scheme::LetheKms, Lethe};
+use allocator::Allocator;
+use crypter::Crypter;
+use embedded_io::blocking::{Read, Seek, Write};
+use hasher::Hasher;
+use kms::KeyManagementScheme;
+use persistence::PersistentStorage;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Storage whose automatic commits can be deferred. Namespace changes span several objects (the
+/// directory, the named object and the superblock), and a commit landing between them, followed
+/// by a crash, could persist a directory that was truncated but never rewritten.
+pub trait DeferCommits {
+    /// Defers (or stops deferring) automatic commits, returning whether they were already
+    /// deferred. Any commit that came due in the meantime is made once they stop being deferred.
+    fn defer_commits(&mut self, defer: bool) -> Result<bool, Error>;
+}
+
+impl<S, P, A, R, C, H, const E: usize, const D: usize, K, M> DeferCommits
+    for Lethe<S, P, A, R, C, H, E, D, K, M>
+where
+    S: Enclave,
+    P: PersistentStorage<Id = u64>,
+    for<'a> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'a>,
+    for<'a> <P as PersistentStorage>::Io<'a>: Read + Write + Seek,
+    for<'a> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'a>,
+    R: RngCore + CryptoRng + Clone + Default,
+    C: Crypter,
+    H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
+{
+    fn defer_commits(&mut self, defer: bool) -> Result<bool, Error> {
+        let deferred = self.suspend_auto_persist(defer);
+        if !defer {
+            self.persist_if_due()?;
+        }
+        Ok(deferred)
+    }
+}
+
+/// The kind of object a name refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// A directory entry, as returned by `Namespace::readdir()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub objid: u64,
+    pub kind: EntryKind,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Entry {
+    objid: u64,
+    kind: EntryKind,
+}
+
+// The contents of a directory object.
+type Directory = BTreeMap<String, Entry>;
+
+// What gets stored in the superblock object.
+#[derive(Serialize, Deserialize)]
+struct Superblock<A> {
+    allocator: A,
+    root: u64,
+}
+
+/// Maps path-like names (e.g. `/logs/2023/app.log`) to object IDs.
+///
+/// Directories are ordinary objects in the underlying storage holding their serialized entries,
+/// so over `Lethe` they're encrypted like any other object. Every change to a directory rewrites
+/// it from scratch after truncating it to nothing, so removed entries are securely forgotten
+/// along with the keys that encrypted them. Unlinking a name destroys the object it refers to.
+///
+/// The namespace allocates the object IDs it uses, starting from the superblock object that
+/// records the allocator's state and the root directory. Objects created in the underlying
+/// storage by other means must not collide with these IDs.
+///
+/// Each change is made with the storage's automatic commits deferred, so that it's committed
+/// whole or not at all.
+pub struct Namespace<P, A> {
+    storage: P,
+    allocator: A,
+    superblock: u64,
+    root: u64,
+}
+
+impl<P, A> Namespace<P, A>
+where
+    P: PersistentStorage<Id = u64> + DeferCommits,
+    <P as PersistentStorage>::Flags: Default,
+    for<'a> <P as PersistentStorage>::Io<'a>: Read + Write,
+    for<'a> A: Allocator<Id = u64> + Serialize + Deserialize<'a>,
+{
+    /// Creates an empty namespace whose superblock is stored in the `superblock` object.
+    pub fn create(storage: P, mut allocator: A, superblock: u64) -> Result<Self, Error> {
+        allocator.reserve(superblock).map_err(|_| Error::Alloc)?;
+        let root = allocator.alloc().map_err(|_| Error::Alloc)?;
+
+        let mut namespace = Self {
+            storage,
+            allocator,
+            superblock,
+            root,
+        };
+
+        namespace.deferred(|namespace| {
+            let flags = Default::default();
+            namespace
+                .storage
+                .create(&superblock, &flags)
+                .map_err(|_| Error::Io)?;
+            namespace
+                .storage
+                .create(&root, &flags)
+                .map_err(|_| Error::Io)?;
+            namespace.write_dir(root, &Directory::new())?;
+            namespace.write_superblock()
+        })?;
+
+        Ok(namespace)
+    }
+
+    /// Opens the namespace whose superblock is stored in the `superblock` object.
+    pub fn open(mut storage: P, superblock: u64) -> Result<Self, Error> {
+        let ser = read_object(&mut storage, superblock)?;
+        let Superblock { allocator, root } = bincode::deserialize(&ser)?;
+
+        Ok(Self {
+            storage,
+            allocator,
+            superblock,
+            root,
+        })
+    }
+
+    /// Returns the underlying storage, through which named objects are read and written.
+    pub fn storage(&mut self) -> &mut P {
+        &mut self.storage
+    }
+
+    /// Consumes the namespace, returning the underlying storage.
+    pub fn into_inner(self) -> P {
+        self.storage
+    }
+
+    /// Returns the ID of the object named by `path`.
+    pub fn lookup(&mut self, path: &str) -> Result<u64, Error> {
+        Ok(self.resolve(path)?.objid)
+    }
+
+    /// Creates an empty object named by `path`, returning its ID. The parent directory must
+    /// already exist.
+    pub fn create_object(
+        &mut self,
+        path: &str,
+        flags: &<P as PersistentStorage>::Flags,
+    ) -> Result<u64, Error> {
+        self.deferred(|namespace| namespace.insert(path, EntryKind::File, flags))
+    }
+
+    /// Creates an empty directory named by `path`, returning its ID. The parent directory must
+    /// already exist.
+    pub fn mkdir(&mut self, path: &str) -> Result<u64, Error> {
+        self.deferred(|namespace| namespace.insert(path, EntryKind::Directory, &Default::default()))
+    }
+
+    /// Lists the entries of the directory named by `path`, in name order.
+    pub fn readdir(&mut self, path: &str) -> Result<Vec<DirEntry>, Error> {
+        let entry = self.resolve(path)?;
+        if entry.kind != EntryKind::Directory {
+            return Err(Error::NotADirectory);
+        }
+
+        Ok(self
+            .read_dir(entry.objid)?
+            .into_iter()
+            .map(|(name, entry)| DirEntry {
+                name,
+                objid: entry.objid,
+                kind: entry.kind,
+            })
+            .collect())
+    }
+
+    /// Removes the name `path` and destroys the object it refers to. Directories must be empty.
+    pub fn unlink(&mut self, path: &str) -> Result<(), Error> {
+        self.deferred(|namespace| namespace.remove(path))
+    }
+
+    // Runs a change spanning several objects with automatic commits deferred.
+    fn deferred<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        let deferred = self.storage.defer_commits(true)?;
+        let res = f(self);
+        let resumed = self.storage.defer_commits(deferred);
+        let res = res?;
+        resumed?;
+        Ok(res)
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let (parent, name) = self.resolve_parent(path)?;
+        let mut dir = self.read_dir(parent)?;
+        let entry = *dir.get(name).ok_or(Error::MissingName)?;
+
+        if entry.kind == EntryKind::Directory && !self.read_dir(entry.objid)?.is_empty() {
+            return Err(Error::DirectoryNotEmpty);
+        }
+
+        // Forget the entry before the object, so a failure can't leave it dangling.
+        dir.remove(name);
+        self.write_dir(parent, &dir)?;
+
+        self.storage.destroy(&entry.objid).map_err(|_| Error::Io)?;
+        self.allocator
+            .dealloc(entry.objid)
+            .map_err(|_| Error::Dealloc)?;
+        self.write_superblock()
+    }
+
+    fn insert(
+        &mut self,
+        path: &str,
+        kind: EntryKind,
+        flags: &<P as PersistentStorage>::Flags,
+    ) -> Result<u64, Error> {
+        let (parent, name) = self.resolve_parent(path)?;
+        let mut dir = self.read_dir(parent)?;
+        if dir.contains_key(name) {
+            return Err(Error::ObjectExists);
+        }
+
+        let objid = self.allocator.alloc().map_err(|_| Error::Alloc)?;
+        self.storage.create(&objid, flags).map_err(|_| Error::Io)?;
+        if kind == EntryKind::Directory {
+            self.write_dir(objid, &Directory::new())?;
+        }
+
+        dir.insert(name.to_string(), Entry { objid, kind });
+        self.write_dir(parent, &dir)?;
+        self.write_superblock()?;
+
+        Ok(objid)
+    }
+
+    // Walks `path` from the root directory.
+    fn resolve(&mut self, path: &str) -> Result<Entry, Error> {
+        let mut entry = Entry {
+            objid: self.root,
+            kind: EntryKind::Directory,
+        };
+
+        for name in components(path)? {
+            if entry.kind != EntryKind::Directory {
+                return Err(Error::NotADirectory);
+            }
+            entry = *self
+                .read_dir(entry.objid)?
+                .get(name)
+                .ok_or(Error::MissingName)?;
+        }
+
+        Ok(entry)
+    }
+
+    // Resolves the directory containing `path`, returning it with the final name.
+    fn resolve_parent<'p>(&mut self, path: &'p str) -> Result<(u64, &'p str), Error> {
+        let mut names = components(path)?;
+        let name = names.pop().ok_or(Error::InvalidPath)?;
+
+        let parent = self.resolve(&names.join("/"))?;
+        if parent.kind != EntryKind::Directory {
+            return Err(Error::NotADirectory);
+        }
+
+        Ok((parent.objid, name))
+    }
+
+    fn read_dir(&mut self, objid: u64) -> Result<Directory, Error> {
+        let ser = read_object(&mut self.storage, objid)?;
+        Ok(bincode::deserialize(&ser)?)
+    }
+
+    fn write_dir(&mut self, objid: u64, dir: &Directory) -> Result<(), Error> {
+        let ser = bincode::serialize(dir)?;
+        self.write_object(objid, &ser)
+    }
+
+    fn write_superblock(&mut self) -> Result<(), Error> {
+        let ser = bincode::serialize(&Superblock {
+            allocator: &self.allocator,
+            root: self.root,
+        })?;
+        self.write_object(self.superblock, &ser)
+    }
+
+    // Truncating first means the old contents are forgotten rather than overwritten in place.
+    fn write_object(&mut self, objid: u64, data: &[u8]) -> Result<(), Error> {
+        self.storage.truncate(&objid, 0).map_err(|_| Error::Io)?;
+        self.storage
+            .write_handle(&objid)
+            .map_err(|_| Error::Io)?
+            .write_all(data)
+            .map_err(|_| Error::Io)
+    }
+}
+
+fn read_object<P>(storage: &mut P, objid: u64) -> Result<Vec<u8>, Error>
+where
+    P: PersistentStorage<Id = u64>,
+    for<'a> <P as PersistentStorage>::Io<'a>: Read,
+{
+    let mut ser = vec![];
+    storage
+        .read_handle(&objid)
+        .map_err(|_| Error::Io)?
+        .read_to_end(&mut ser)
+        .map_err(|_| Error::Io)?;
+    Ok(ser)
+}
+
+// Splits a path into its names, ignoring empty components so that `/a//b/` is `a/b`.
+fn components(path: &str) -> Result<Vec<&str>, Error> {
+    let names: Vec<&str> = path.split('/').filter(|name| !name.is_empty()).collect();
+    if names.iter().any(|name| *name == "." || *name == "..") {
+        return Err(Error::InvalidPath);
+    }
+    Ok(names)
+}
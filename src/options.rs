@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// Options for creating an object with `Lethe::create_with_options()`.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectOptions {
+    pub(crate) khf_fanouts: Option<Vec<u64>>,
+    pub(crate) expiry: Option<SystemTime>,
+}
+
+impl ObjectOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the fanouts of the object's `Khf`, overriding the store-wide default. Small objects
+    /// are best served by shallow trees, large ones by deeper or wider trees.
+    pub fn khf_fanouts(&mut self, fanouts: &[u64]) -> &mut Self {
+        self.khf_fanouts = Some(fanouts.to_vec());
+        self
+    }
+
+    /// Sets when the object expires. It's destroyed at the first commit after then, bypassing
+    /// the trash.
+    pub fn expiry(&mut self, when: SystemTime) -> &mut Self {
+        self.expiry = Some(when);
+        self
+    }
+}
+
+/// Thresholds past which `Lethe` commits on its own, bounding how long deleted data stays
+/// recoverable. There's no timer: thresholds are only checked at operation boundaries and by
+/// `Lethe::persist_if_due()`, so an idle store doesn't commit until it's next used, and the
+/// interval can be overrun by as long as the store sits idle. Bytes written through a handle
+/// are checked by the next operation, so a single handle can write past the byte threshold;
+/// `Lethe::write_at()` checks right after its write instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct AutoPersist {
+    pub(crate) ops: Option<u64>,
+    pub(crate) bytes: Option<u64>,
+    pub(crate) destroys: Option<u64>,
+    pub(crate) interval: Option<Duration>,
+}
+
+/// How object lengths are padded in the underlying storage, so that it doesn't learn exactly how
+/// long objects are. Padding is encrypted like any other data, and the logical length of each
+/// object is kept in its encrypted metadata.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaddingPolicy {
+    /// Objects are stored at exactly their logical length.
+    #[default]
+    None,
+    /// Lengths are rounded up to a multiple of the block size.
+    Block,
+    /// Lengths are rounded up to the next power of two, leaking only their order of magnitude.
+    PowerOfTwo,
+    /// Lengths are rounded up to a multiple of a fixed size class. Even empty objects take up
+    /// one size class.
+    Fixed(u64),
+}
+
+impl PaddingPolicy {
+    /// Returns the length an object of logical length `len` is padded to.
+    pub fn padded_len(&self, len: u64, block_size: u64) -> u64 {
+        let round_up = |len: u64, multiple: u64| len.div_ceil(multiple) * multiple;
+
+        match *self {
+            Self::None => len,
+            Self::Block => round_up(len, block_size),
+            Self::PowerOfTwo if len > 0 => len.checked_next_power_of_two().unwrap_or(len),
+            Self::PowerOfTwo => 0,
+            Self::Fixed(size) if size > 0 => round_up(len.max(1), size),
+            Self::Fixed(_) => len,
+        }
+    }
+}
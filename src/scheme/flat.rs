@@ -0,0 +1,170 @@
+use crate::{scheme::LetheKms, Key};
+use khf::Consolidation;
+use kms::KeyManagementScheme;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::Infallible,
+    marker::PhantomData,
+};
+use zeroize::Zeroizing;
+
+/// A flat key-per-block table. Every key is independently random, so updating a key never
+/// fragments the scheme, but the table grows linearly with the number of keys. Keys are zeroized
+/// as they're replaced or dropped.
+#[derive(Serialize, Deserialize)]
+pub struct FlatKms<R, const N: usize> {
+    keys: BTreeMap<u64, Zeroizing<Vec<u8>>>,
+    updated: BTreeSet<u64>,
+    #[serde(skip)]
+    pd: PhantomData<R>,
+}
+
+impl<R, const N: usize> FlatKms<R, N>
+where
+    R: RngCore + CryptoRng + Default,
+{
+    pub fn new() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+            updated: BTreeSet::new(),
+            pd: PhantomData,
+        }
+    }
+
+    fn random_key() -> Zeroizing<Vec<u8>> {
+        let mut key = Zeroizing::new(vec![0; N]);
+        R::default().fill_bytes(&mut key);
+        key
+    }
+}
+
+impl<R, const N: usize> Default for FlatKms<R, N>
+where
+    R: RngCore + CryptoRng + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R, const N: usize> Clone for FlatKms<R, N> {
+    fn clone(&self) -> Self {
+        Self {
+            keys: self.keys.clone(),
+            updated: self.updated.clone(),
+            pd: PhantomData,
+        }
+    }
+}
+
+impl<R, const N: usize> KeyManagementScheme for FlatKms<R, N>
+where
+    R: RngCore + CryptoRng + Default,
+{
+    type Key = Key<N>;
+    type KeyId = u64;
+    type Error = Infallible;
+
+    fn derive(&mut self, keyid: Self::KeyId) -> Result<Self::Key, Self::Error> {
+        let key = self.keys.entry(keyid).or_insert_with(Self::random_key);
+        let mut out = [0; N];
+        out.copy_from_slice(key);
+        Ok(out)
+    }
+
+    fn update(&mut self, keyid: Self::KeyId) -> Result<Self::Key, Self::Error> {
+        self.keys.insert(keyid, Self::random_key());
+        self.updated.insert(keyid);
+        self.derive(keyid)
+    }
+
+    fn commit(&mut self) -> Vec<Self::KeyId> {
+        std::mem::take(&mut self.updated).into_iter().collect()
+    }
+}
+
+impl<R, const N: usize> LetheKms<N> for FlatKms<R, N>
+where
+    R: RngCore + CryptoRng + Default,
+{
+    fn with_fanouts(_fanouts: &[u64]) -> Self {
+        Self::new()
+    }
+
+    // Keys are already independent, so there's nothing to consolidate.
+    fn consolidate(&mut self, _mechanism: Consolidation) -> Vec<u64> {
+        vec![]
+    }
+
+    fn truncate(&mut self, keys: u64) {
+        self.keys.split_off(&keys);
+        self.updated.split_off(&keys);
+    }
+
+    fn fragmentation(&self) -> u64 {
+        self.keys.len() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::BlockCryptIo;
+    use anyhow::Result;
+    use crypter::openssl::Aes256Ctr;
+    use embedded_io::{
+        adapters::FromStd,
+        blocking::{Read, Seek, Write},
+        SeekFrom,
+    };
+    use hasher::openssl::SHA3_256_MD_SIZE;
+    use rand::rngs::ThreadRng;
+    use tempfile::NamedTempFile;
+
+    const BLOCK_SIZE: usize = 4096;
+    const KEY_SIZE: usize = SHA3_256_MD_SIZE;
+
+    // Writes 4 blocks of 'a's, then 4 'b's at offset 3.
+    #[test]
+    fn offset_write() -> Result<()> {
+        let mut kms = FlatKms::<ThreadRng, KEY_SIZE>::new();
+
+        let mut blockio = BlockCryptIo::<
+            FromStd<NamedTempFile>,
+            FlatKms<ThreadRng, KEY_SIZE>,
+            Aes256Ctr,
+            BLOCK_SIZE,
+            KEY_SIZE,
+        >::new(FromStd::new(NamedTempFile::new()?), &mut kms);
+
+        blockio.write_all(&['a' as u8; 4 * BLOCK_SIZE])?;
+        blockio.seek(SeekFrom::Start(3))?;
+        blockio.write_all(&['b' as u8; 4])?;
+
+        let mut buf = vec![0; 4 * BLOCK_SIZE];
+        blockio.seek(SeekFrom::Start(0))?;
+        blockio.read_exact(&mut buf)?;
+
+        assert_eq!(&buf[..3], &['a' as u8; 3]);
+        assert_eq!(&buf[3..7], &['b' as u8; 4]);
+        assert_eq!(&buf[7..], &['a' as u8; 4 * BLOCK_SIZE - 7]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_and_commit() {
+        let mut kms = FlatKms::<ThreadRng, KEY_SIZE>::new();
+
+        for block in 0..8 {
+            kms.update(block).unwrap();
+        }
+        kms.truncate(4);
+
+        assert_eq!(kms.fragmentation(), 4);
+        assert_eq!(kms.commit(), vec![0, 1, 2, 3]);
+        assert!(kms.commit().is_empty());
+    }
+}
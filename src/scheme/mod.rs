@@ -0,0 +1,55 @@
+mod flat;
+
+pub use flat::FlatKms;
+
+use crate::Key;
+use hasher::Hasher;
+use khf::{Consolidation, Khf};
+use kms::KeyManagementScheme;
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A key management scheme that can be used by `Lethe`, either as the master scheme or as the
+/// scheme for individual objects. Schemes are persisted by serializing them, so they must also
+/// be (de)serializable.
+pub trait LetheKms<const N: usize>:
+    KeyManagementScheme<KeyId = u64, Key = Key<N>> + Clone + Serialize + DeserializeOwned
+{
+    /// Creates a new instance of the scheme. Tree-based schemes use `fanouts` to determine their
+    /// shape, others are free to ignore it.
+    fn with_fanouts(fanouts: &[u64]) -> Self;
+
+    /// Consolidates the scheme using the specified `mechanism`, returning the IDs of the keys
+    /// that were changed by the consolidation.
+    fn consolidate(&mut self, mechanism: Consolidation) -> Vec<u64>;
+
+    /// Truncates the scheme to only manage `keys` keys.
+    fn truncate(&mut self, keys: u64);
+
+    /// Returns the number of keys the scheme actually holds.
+    fn fragmentation(&self) -> u64;
+}
+
+impl<R, H, const N: usize> LetheKms<N> for Khf<R, H, N>
+where
+    R: RngCore + CryptoRng + Clone + Default,
+    H: Hasher<N>,
+    Khf<R, H, N>:
+        KeyManagementScheme<KeyId = u64, Key = Key<N>> + Clone + Serialize + DeserializeOwned,
+{
+    fn with_fanouts(fanouts: &[u64]) -> Self {
+        Khf::new(fanouts, R::default())
+    }
+
+    fn consolidate(&mut self, mechanism: Consolidation) -> Vec<u64> {
+        Khf::consolidate(self, mechanism)
+    }
+
+    fn truncate(&mut self, keys: u64) {
+        Khf::truncate(self, keys)
+    }
+
+    fn fragmentation(&self) -> u64 {
+        Khf::fragmentation(self)
+    }
+}
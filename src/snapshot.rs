@@ -0,0 +1,19 @@
+use crate::metadata::Metadata;
+use std::collections::HashMap;
+
+/// A read-only view of one or more objects, as they were when the snapshot was taken.
+///
+/// Snapshots only live in memory. Each snapshotted object gets a raw copy of its ciphertext in
+/// the underlying storage, readable only with the copy of its `Khf` held here. Since that copy
+/// is never persisted, the keys it retains are forgotten as soon as the snapshot is released (or
+/// the store is closed), and the ciphertext copies are destroyed along with it.
+pub(crate) struct Snapshot<K, I, F> {
+    pub(crate) objects: HashMap<u64, SnapshotObject<K, I, F>>,
+}
+
+pub(crate) struct SnapshotObject<K, I, F> {
+    /// The ID of the ciphertext copy in the underlying storage.
+    pub(crate) map_id: u64,
+    pub(crate) khf: K,
+    pub(crate) metadata: Metadata<I, F>,
+}
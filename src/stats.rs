@@ -0,0 +1,33 @@
+/// Store-wide statistics, as reported by `Lethe::stats()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LetheStats {
+    /// The current (uncommitted) epoch.
+    pub epoch: u64,
+    /// Number of live objects.
+    pub objects: u64,
+    /// Number of keys held by the master `Khf`.
+    pub master_khf_keys: u64,
+    /// Size of the serialized master `Khf` in bytes.
+    pub master_khf_size: u64,
+    /// Number of object `Khf`s resident in the cache.
+    pub cached_khfs: u64,
+    /// Number of objects' metadata resident in the cache.
+    pub cached_metadata: u64,
+}
+
+/// Per-object statistics, as reported by `Lethe::object_stats()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectStats {
+    /// Number of blocks in the object.
+    pub blocks: u64,
+    /// Number of keys held by the object `Khf`. This is a measure of its fragmentation.
+    pub khf_keys: u64,
+    /// Depth of the object `Khf`'s trees.
+    pub khf_depth: u64,
+    /// Size of the serialized object `Khf` in bytes.
+    pub khf_size: u64,
+    /// The epoch in which the object was last updated.
+    pub epoch: u64,
+    /// Whether the object `Khf` was resident in the cache before it was queried.
+    pub cached: bool,
+}
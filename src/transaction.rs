@@ -0,0 +1,292 @@
+use crate::{enclave::Enclave, error::Error, options::ObjectOptions, scheme::LetheKms, Lethe};
+use allocator::Allocator;
+use crypter::Crypter;
+use embedded_io::{
+    blocking::{Read, Seek, Write},
+    SeekFrom,
+};
+use hasher::Hasher;
+use kms::KeyManagementScheme;
+use persistence::PersistentStorage;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+enum Op<F> {
+    Create {
+        objid: u64,
+        flags: F,
+        options: ObjectOptions,
+    },
+    Write {
+        objid: u64,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    Truncate {
+        objid: u64,
+        size: u64,
+    },
+    Destroy {
+        objid: u64,
+    },
+}
+
+/// A set of operations across objects that are committed together, in a single epoch, or not at
+/// all. Operations are only staged until `commit()`, so nothing is applied if the transaction is
+/// dropped, and a `persist_state()` in the meantime can't capture half of it.
+pub struct Transaction<'a, S, P, A, R, C, H, const E: usize, const D: usize, K, M>
+where
+    S: Enclave,
+    P: PersistentStorage<Id = u64>,
+    for<'b> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'b>,
+    for<'b> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'b>,
+    for<'b> <P as PersistentStorage>::Io<'b>: Read + Write + Seek,
+    for<'b> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'b>,
+    R: RngCore + CryptoRng + Clone + Default,
+    C: Crypter,
+    H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
+{
+    lethe: &'a mut Lethe<S, P, A, R, C, H, E, D, K, M>,
+    ops: Vec<Op<<P as PersistentStorage>::Flags>>,
+}
+
+impl<'a, S, P, A, R, C, H, const E: usize, const D: usize, K, M>
+    Transaction<'a, S, P, A, R, C, H, E, D, K, M>
+where
+    S: Enclave,
+    P: PersistentStorage<Id = u64>,
+    for<'b> <P as PersistentStorage>::Flags: Default + Clone + Serialize + Deserialize<'b>,
+    for<'b> <P as PersistentStorage>::Info: Default + Clone + Serialize + Deserialize<'b>,
+    for<'b> <P as PersistentStorage>::Io<'b>: Read + Write + Seek,
+    for<'b> A: Allocator<Id = u64> + Default + Serialize + Deserialize<'b>,
+    R: RngCore + CryptoRng + Clone + Default,
+    C: Crypter,
+    H: Hasher<E>,
+    K: LetheKms<E>,
+    M: LetheKms<E>,
+    Error: From<<K as KeyManagementScheme>::Error> + From<<M as KeyManagementScheme>::Error>,
+{
+    pub(crate) fn new(lethe: &'a mut Lethe<S, P, A, R, C, H, E, D, K, M>) -> Self {
+        Self { lethe, ops: vec![] }
+    }
+
+    /// Stages the creation of an object.
+    pub fn create(&mut self, objid: u64, flags: <P as PersistentStorage>::Flags) -> &mut Self {
+        self.create_with_options(objid, flags, &ObjectOptions::default())
+    }
+
+    /// Stages the creation of an object, overriding the store-wide defaults with `options`.
+    pub fn create_with_options(
+        &mut self,
+        objid: u64,
+        flags: <P as PersistentStorage>::Flags,
+        options: &ObjectOptions,
+    ) -> &mut Self {
+        self.ops.push(Op::Create {
+            objid,
+            flags,
+            options: options.clone(),
+        });
+        self
+    }
+
+    /// Stages a write of `data` to an object at `offset`.
+    pub fn write(&mut self, objid: u64, offset: u64, data: &[u8]) -> &mut Self {
+        self.ops.push(Op::Write {
+            objid,
+            offset,
+            data: data.to_vec(),
+        });
+        self
+    }
+
+    /// Stages the truncation of an object.
+    pub fn truncate(&mut self, objid: u64, size: u64) -> &mut Self {
+        self.ops.push(Op::Truncate { objid, size });
+        self
+    }
+
+    /// Stages the destruction of an object.
+    pub fn destroy(&mut self, objid: u64) -> &mut Self {
+        self.ops.push(Op::Destroy { objid });
+        self
+    }
+
+    /// Applies the staged operations, in order, and commits them with a single
+    /// `persist_state()`. Changes made outside the transaction since the last commit are
+    /// committed on their own first.
+    ///
+    /// The operations are checked against the objects that exist before anything is applied.
+    /// Existing objects are written and truncated through copies that only replace them once
+    /// every write has succeeded, and destroys are applied last, so if applying fails regardless
+    /// (e.g. due to an IO error), the objects are left as they were. The copies are made with
+    /// `Lethe::clone_object()`, so they keep the objects' flags, info and expiry. The price is a
+    /// full copy of each existing object the transaction writes to, however little of it is
+    /// written: blocks aren't shared between an object and its copy.
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.validate()?;
+
+        if self.lethe.uncommitted() {
+            self.lethe.persist_state()?;
+        }
+
+        // Committing partway through would break atomicity.
+        let suspended = self.lethe.suspend_auto_persist(true);
+        let res = self.apply();
+        self.lethe.suspend_auto_persist(suspended);
+        res?;
+
+        self.lethe.persist_state()
+    }
+
+    /// Discards the staged operations. Dropping the transaction does the same.
+    pub fn abort(self) {}
+
+    // Checks that every operation applies to an object that exists at that point. Since destroys
+    // are deferred, destroyed objects can't be recreated in the same transaction.
+    fn validate(&self) -> Result<(), Error> {
+        let mut created = HashSet::new();
+        let mut destroyed = HashSet::new();
+        let exists = |objid: &u64, created: &HashSet<u64>, destroyed: &HashSet<u64>| {
+            created.contains(objid) || (self.lethe.exists(*objid) && !destroyed.contains(objid))
+        };
+
+        for op in &self.ops {
+            match op {
+                Op::Create { objid, .. } => {
+                    if exists(objid, &created, &destroyed) || destroyed.contains(objid) {
+                        return Err(Error::ObjectExists);
+                    }
+                    created.insert(*objid);
+                }
+                Op::Write { objid, .. } | Op::Truncate { objid, .. } => {
+                    if !exists(objid, &created, &destroyed) {
+                        return Err(Error::MissingKhf);
+                    }
+                }
+                Op::Destroy { objid } => {
+                    if !exists(objid, &created, &destroyed) {
+                        return Err(Error::MissingKhf);
+                    }
+                    created.remove(objid);
+                    destroyed.insert(*objid);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self) -> Result<(), Error> {
+        let mut created = vec![];
+        let mut shadows = BTreeMap::new();
+        let mut destroyed = vec![];
+
+        if let Err(err) = self.stage(&mut created, &mut shadows, &mut destroyed) {
+            // Nothing the transaction touched has changed, so forgetting what it created is
+            // enough to undo it.
+            for objid in created.into_iter().chain(shadows.into_values()) {
+                if let Some(entry) = self.lethe.mappings.remove(&objid) {
+                    let _ = self.lethe.purge(entry);
+                }
+            }
+            return Err(err);
+        }
+
+        // Swap in the written copies, then securely delete the old versions along with the
+        // destroyed objects.
+        for (&objid, &shadow) in &shadows {
+            self.lethe.exchange(objid, shadow)?;
+        }
+        for shadow in shadows.into_values() {
+            let entry = self.lethe.mappings.remove(&shadow).unwrap();
+            self.lethe.purge(entry)?;
+        }
+        for objid in destroyed {
+            self.lethe.destroy(&objid)?;
+        }
+
+        Ok(())
+    }
+
+    // Applies every operation but the destroys, which are only collected. Writes and truncations
+    // of objects that existed before the transaction go to shadow copies of them.
+    fn stage(
+        &mut self,
+        created: &mut Vec<u64>,
+        shadows: &mut BTreeMap<u64, u64>,
+        destroyed: &mut Vec<u64>,
+    ) -> Result<(), Error> {
+        let ops = std::mem::take(&mut self.ops);
+        let taken: HashSet<u64> = ops
+            .iter()
+            .map(|op| match op {
+                Op::Create { objid, .. }
+                | Op::Write { objid, .. }
+                | Op::Truncate { objid, .. }
+                | Op::Destroy { objid } => *objid,
+            })
+            .collect();
+
+        for op in ops {
+            match op {
+                Op::Create {
+                    objid,
+                    flags,
+                    options,
+                } => {
+                    self.lethe.create_with_options(objid, &flags, &options)?;
+                    created.push(objid);
+                }
+                Op::Write {
+                    objid,
+                    offset,
+                    data,
+                } => {
+                    let target = self.target(objid, created, shadows, &taken)?;
+                    let mut io = self.lethe.write_handle(&target)?;
+                    io.seek(SeekFrom::Start(offset)).map_err(|_| Error::Io)?;
+                    io.write_all(&data).map_err(|_| Error::Io)?;
+                }
+                Op::Truncate { objid, size } => {
+                    let target = self.target(objid, created, shadows, &taken)?;
+                    self.lethe.truncate(&target, size)?;
+                }
+                Op::Destroy { objid } => destroyed.push(objid),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the object that changes to `objid` are made to, cloning it into a shadow copy
+    // under an unused ID the first time an existing object is changed. The shadow is created with
+    // the object's flags, so exchanging it in doesn't change them.
+    fn target(
+        &mut self,
+        objid: u64,
+        created: &[u64],
+        shadows: &mut BTreeMap<u64, u64>,
+        taken: &HashSet<u64>,
+    ) -> Result<u64, Error> {
+        if created.contains(&objid) {
+            return Ok(objid);
+        }
+        if let Some(&shadow) = shadows.get(&objid) {
+            return Ok(shadow);
+        }
+
+        let shadow = (0..=u64::MAX)
+            .rev()
+            .find(|id| !self.lethe.exists(*id) && !taken.contains(id))
+            .ok_or(Error::Alloc)?;
+        shadows.insert(objid, shadow);
+        self.lethe.clone_object(objid, shadow)?;
+
+        Ok(shadow)
+    }
+}
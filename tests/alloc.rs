@@ -0,0 +1,46 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, RandLethe, TestStore, BLOCK_SIZE};
+use embedded_io::blocking::Write;
+use persistence::PersistentStorage;
+
+const OBJECTS: u64 = 64;
+
+#[test]
+fn randomized_ids() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = RandLethe::new(store.enclave()?, store.storage()?);
+
+        let mut ids = vec![];
+        for objid in 0..OBJECTS {
+            lethe.create(&objid, &())?;
+            lethe
+                .write_handle(&objid)?
+                .write_all(&[objid as u8; BLOCK_SIZE])?;
+
+            let entry = lethe.get_khf_mapping(objid).unwrap();
+            ids.extend([entry.map_id, entry.khf_id, entry.meta_id]);
+        }
+        lethe.persist_state()?;
+
+        // Creation order isn't visible in the IDs.
+        assert!(ids.windows(2).any(|pair| pair[1] < pair[0]));
+
+        // The IDs stay within the smallest range that holds them, so the master `Khf` holds at
+        // most one key per ID in that range.
+        assert!(ids.iter().all(|&id| id < 256));
+        assert!(lethe.stats()?.master_khf_keys <= 256);
+    }
+
+    let mut lethe = RandLethe::new(store.enclave()?, store.storage()?);
+    lethe.load_state()?;
+
+    for objid in 0..OBJECTS {
+        assert_eq!(read_all(&mut lethe, objid)?, vec![objid as u8; BLOCK_SIZE]);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use anyhow::Result;
+use common::{DirLethe, TestStore};
+use embedded_io::blocking::Write;
+use persistence::PersistentStorage;
+use std::time::Duration;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn thresholds() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .persist_after_bytes(100)
+        .persist_after_destroys(1)
+        .build(store.enclave()?, store.storage()?)?;
+    lethe.create(&OBJID, &())?;
+    let epoch = lethe.epoch();
+
+    lethe.write_handle(&OBJID)?.write_all(&[0; 60])?;
+    lethe.write_handle(&OBJID)?.write_all(&[0; 60])?;
+    assert_eq!(lethe.epoch(), epoch);
+
+    // The bytes written so far are counted when the next handle is opened.
+    lethe.write_handle(&OBJID)?;
+    assert_eq!(lethe.epoch(), epoch + 1);
+
+    // Writes made with `write_at()` are counted as soon as they're done.
+    lethe.write_at(OBJID, 0, &[0; 60])?;
+    assert_eq!(lethe.epoch(), epoch + 1);
+    lethe.write_at(OBJID, 60, &[0; 60])?;
+    assert_eq!(lethe.epoch(), epoch + 2);
+
+    // Destroys commit straight away, so the keys are gone on return.
+    lethe.destroy(&OBJID)?;
+    assert_eq!(lethe.epoch(), epoch + 3);
+    assert!(lethe.since_last_commit() < Duration::from_secs(60));
+
+    Ok(())
+}
+
+// Each operation counts once, however many writes it's made of, and commits once it's finished.
+#[test]
+fn ops() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .persist_after_ops(2)
+        .build(store.enclave()?, store.storage()?)?;
+    let epoch = lethe.epoch();
+
+    lethe.create(&OBJID, &())?;
+    lethe.write_handle(&OBJID)?.write_all(&[1; 100])?;
+    assert_eq!(lethe.epoch(), epoch + 1);
+
+    lethe.truncate(&OBJID, 10)?;
+    assert_eq!(lethe.epoch(), epoch + 1);
+    lethe.shred_range(OBJID, 0, 5)?;
+    assert_eq!(lethe.epoch(), epoch + 2);
+
+    lethe.clone_object(OBJID, OBJID + 1)?;
+    assert_eq!(lethe.epoch(), epoch + 2);
+    lethe.rename(OBJID + 1, OBJID, true)?;
+    assert_eq!(lethe.epoch(), epoch + 3);
+
+    Ok(())
+}
+
+#[test]
+fn interval() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .persist_interval(Duration::ZERO)
+        .build(store.enclave()?, store.storage()?)?;
+    let epoch = lethe.epoch();
+    assert!(lethe.persist_if_due()?);
+    assert_eq!(lethe.epoch(), epoch + 1);
+
+    Ok(())
+}
+
+#[test]
+fn transaction() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .persist_after_ops(1)
+        .build(store.enclave()?, store.storage()?)?;
+    let epoch = lethe.epoch();
+
+    // A transaction only commits once, at the end.
+    let mut tx = lethe.transaction();
+    tx.create(OBJID, ())
+        .write(OBJID, 0, b"data")
+        .truncate(OBJID, 2);
+    tx.commit()?;
+    assert_eq!(lethe.epoch(), epoch + 1);
+
+    Ok(())
+}
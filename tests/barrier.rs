@@ -0,0 +1,71 @@
+mod common;
+
+use anyhow::Result;
+use common::{DirLethe, TestStore};
+use embedded_io::blocking::Write;
+use lethe::enclave::Enclave;
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn forget_barrier() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .grace_period(10)
+        .build(store.enclave()?, store.storage()?)?;
+    lethe.create(&OBJID, &())?;
+    lethe.write_handle(&OBJID)?.write_all(b"secret")?;
+    lethe.persist_state()?;
+
+    // Nothing is pending, so there's nothing to commit.
+    let epoch = lethe.epoch();
+    let counter = lethe.enclave_mut().counter()?;
+    lethe.forget_barrier(true)?;
+    assert_eq!(lethe.enclave_mut().counter()?, counter);
+
+    // The destroy is committed, but the trash is only emptied on request.
+    let map_id = lethe.get_khf_mapping(OBJID).unwrap().map_id;
+    lethe.destroy(&OBJID)?;
+    lethe.forget_barrier(false)?;
+    assert_eq!(lethe.enclave_mut().counter()?, counter + 1);
+    assert_eq!(lethe.trashed(), vec![OBJID]);
+
+    // The barrier doesn't wait out the grace period, or advance the epoch.
+    lethe.forget_barrier(true)?;
+    assert_eq!(lethe.enclave_mut().counter()?, counter + 2);
+    assert_eq!(lethe.epoch(), epoch);
+    assert!(lethe.trashed().is_empty());
+    assert!(!store.path().join(map_id.to_string()).exists());
+
+    Ok(())
+}
+
+// Deletions made without counting as operations still need committing.
+#[test]
+fn after_load() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = DirLethe::options()
+            .grace_period(10)
+            .build(store.enclave()?, store.storage()?)?;
+        lethe.create(&OBJID, &())?;
+        lethe.destroy(&OBJID)?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = DirLethe::options()
+        .grace_period(10)
+        .build(store.enclave()?, store.storage()?)?;
+    lethe.load_state()?;
+    assert_eq!(lethe.trashed(), vec![OBJID]);
+
+    let counter = lethe.enclave_mut().counter()?;
+    lethe.empty_trash()?;
+    lethe.forget_barrier(false)?;
+    assert_eq!(lethe.enclave_mut().counter()?, counter + 1);
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, TestStore, BLOCK_SIZE};
+use embedded_io::blocking::Write;
+use kms::KeyManagementScheme;
+use persistence::PersistentStorage;
+
+const SRC: u64 = 1;
+const DST: u64 = 2;
+
+#[test]
+fn clone_object() -> Result<()> {
+    let store = TestStore::new()?;
+
+    // Large enough to be copied in several chunks.
+    let data: Vec<u8> = (0..100 * BLOCK_SIZE + 10).map(|i| (i % 251) as u8).collect();
+
+    {
+        let mut lethe = store.open()?;
+        lethe.create(&SRC, &())?;
+        lethe.write_handle(&SRC)?.write_all(&data)?;
+        lethe.set_info(&SRC, b"info".to_vec())?;
+
+        lethe.clone_object(SRC, DST)?;
+        assert_eq!(read_all(&mut lethe, DST)?, data);
+        assert_eq!(lethe.get_info(&DST)?, b"info");
+
+        // The clone has its own keys.
+        let src_key = lethe.get_khf_mut(SRC)?.unwrap().derive(0)?;
+        let dst_key = lethe.get_khf_mut(DST)?.unwrap().derive(0)?;
+        assert_ne!(src_key, dst_key);
+
+        // Changing or destroying the source leaves the clone alone.
+        lethe.write_handle(&SRC)?.write_all(&['b' as u8; 10])?;
+        assert_eq!(read_all(&mut lethe, DST)?, data);
+
+        lethe.destroy(&SRC)?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = store.load()?;
+    assert_eq!(read_all(&mut lethe, DST)?, data);
+
+    Ok(())
+}
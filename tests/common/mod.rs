@@ -0,0 +1,342 @@
+#![allow(dead_code)]
+
+use allocator::seq::SequentialAllocator;
+use crypter::openssl::Aes256Ctr;
+use embedded_io::{adapters::FromStd, blocking::Read};
+use hasher::openssl::{Sha3_256, SHA3_256_MD_SIZE};
+use lethe::{
+    alloc::RandomizedAllocator,
+    enclave::{FileEnclave, SimEnclave},
+    scheme::FlatKms,
+    Lethe,
+};
+use persistence::PersistentStorage;
+use rand::rngs::ThreadRng;
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+pub const BLOCK_SIZE: usize = 4096;
+pub const KEY_SIZE: usize = SHA3_256_MD_SIZE;
+
+pub type Enclave = FileEnclave<FromStd<File>>;
+
+/// A `Lethe` instance backed directly by a directory.
+pub type DirLethe = Lethe<
+    Enclave,
+    DirStorage,
+    SequentialAllocator<u64>,
+    ThreadRng,
+    Aes256Ctr,
+    Sha3_256,
+    KEY_SIZE,
+    BLOCK_SIZE,
+>;
+
+/// A `Lethe` instance stacked on top of a `DirLethe`.
+pub type NestedLethe = Lethe<
+    Enclave,
+    DirLethe,
+    SequentialAllocator<u64>,
+    ThreadRng,
+    Aes256Ctr,
+    Sha3_256,
+    KEY_SIZE,
+    BLOCK_SIZE,
+>;
+
+/// A `Lethe` instance backed by a directory, with a simulated TPM as its enclave.
+pub type SimLethe = Lethe<
+    SimEnclave,
+    DirStorage,
+    SequentialAllocator<u64>,
+    ThreadRng,
+    Aes256Ctr,
+    Sha3_256,
+    KEY_SIZE,
+    BLOCK_SIZE,
+>;
+
+/// A `Lethe` instance backed by a directory, with randomized storage IDs.
+pub type RandLethe = Lethe<
+    Enclave,
+    DirStorage,
+    RandomizedAllocator<ThreadRng>,
+    ThreadRng,
+    Aes256Ctr,
+    Sha3_256,
+    KEY_SIZE,
+    BLOCK_SIZE,
+>;
+
+/// A `Lethe` instance backed by a directory, with flat key tables for both the master and
+/// object key schemes.
+pub type FlatLethe = Lethe<
+    Enclave,
+    DirStorage,
+    SequentialAllocator<u64>,
+    ThreadRng,
+    Aes256Ctr,
+    Sha3_256,
+    KEY_SIZE,
+    BLOCK_SIZE,
+    FlatKms<ThreadRng, KEY_SIZE>,
+    FlatKms<ThreadRng, KEY_SIZE>,
+>;
+
+/// A `Lethe` instance backed by a directory whose reads can be made to fail.
+pub type FaultyLethe = Lethe<
+    Enclave,
+    FaultyStorage,
+    SequentialAllocator<u64>,
+    ThreadRng,
+    Aes256Ctr,
+    Sha3_256,
+    KEY_SIZE,
+    BLOCK_SIZE,
+>;
+
+/// A store's objects and enclave, kept in temporary directories that outlive the instances
+/// opened on them.
+pub struct TestStore {
+    dir: TempDir,
+    enclave_dir: TempDir,
+}
+
+impl TestStore {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            dir: TempDir::new()?,
+            enclave_dir: TempDir::new()?,
+        })
+    }
+
+    /// The directory holding the store's objects.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Opens the store's enclave, for instances built with options.
+    pub fn enclave(&self) -> io::Result<Enclave> {
+        enclave(self.enclave_dir.path().join("enclave"))
+    }
+
+    /// Opens the store's objects, for instances built with options.
+    pub fn storage(&self) -> io::Result<DirStorage> {
+        DirStorage::new(self.dir.path())
+    }
+
+    /// Opens a `DirLethe` instance on the store, without loading its state.
+    pub fn open(&self) -> io::Result<DirLethe> {
+        Ok(DirLethe::new(self.enclave()?, self.storage()?))
+    }
+
+    /// Opens a `DirLethe` instance on the store and loads its state.
+    pub fn load(&self) -> anyhow::Result<DirLethe> {
+        let mut lethe = self.open()?;
+        lethe.load_state()?;
+        Ok(lethe)
+    }
+}
+
+/// Reads the whole of an object, from any of the `Lethe` instances above.
+pub fn read_all<P>(lethe: &mut P, objid: u64) -> anyhow::Result<Vec<u8>>
+where
+    P: PersistentStorage<Id = u64>,
+    P::Error: std::error::Error + Send + Sync + 'static,
+    for<'a> P::Io<'a>: Read,
+{
+    let mut buf = vec![];
+    lethe
+        .read_handle(&objid)?
+        .read_to_end(&mut buf)
+        .map_err(|err| anyhow::anyhow!("{err:?}"))?;
+    Ok(buf)
+}
+
+/// Copies the objects in directory `from` to directory `to`.
+pub fn copy_dir(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    fs::create_dir_all(&to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        fs::copy(entry.path(), to.as_ref().join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Opens (or creates) a file-backed enclave.
+pub fn enclave(path: impl AsRef<Path>) -> io::Result<Enclave> {
+    Ok(FileEnclave::new(FromStd::new(
+        File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?,
+    )))
+}
+
+/// A simple storage that keeps each object in its own file.
+pub struct DirStorage {
+    root: PathBuf,
+}
+
+impl DirStorage {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, objid: &u64) -> PathBuf {
+        self.root.join(objid.to_string())
+    }
+
+    fn info_path(&self, objid: &u64) -> PathBuf {
+        self.root.join(format!("{objid}.info"))
+    }
+
+    fn open(&self, objid: &u64, write: bool) -> io::Result<File> {
+        File::options()
+            .read(true)
+            .write(write)
+            .open(self.path(objid))
+    }
+}
+
+impl PersistentStorage for DirStorage {
+    type Id = u64;
+    type Flags = ();
+    type Info = Vec<u8>;
+    type Error = io::Error;
+    type Io<'a> = FromStd<File>;
+
+    // Creating an existing object leaves it intact.
+    fn create(&mut self, objid: &Self::Id, _flags: &Self::Flags) -> Result<(), Self::Error> {
+        File::options()
+            .write(true)
+            .create(true)
+            .open(self.path(objid))?;
+        Ok(())
+    }
+
+    fn destroy(&mut self, objid: &Self::Id) -> Result<(), Self::Error> {
+        match fs::remove_file(self.info_path(objid)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        fs::remove_file(self.path(objid))
+    }
+
+    // Objects without info have empty info, in keeping with `Lethe`.
+    fn get_info(&mut self, objid: &Self::Id) -> Result<Self::Info, Self::Error> {
+        match fs::read(self.info_path(objid)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            res => res,
+        }
+    }
+
+    fn set_info(&mut self, objid: &Self::Id, info: Self::Info) -> Result<(), Self::Error> {
+        fs::write(self.info_path(objid), info)
+    }
+
+    fn read_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        Ok(FromStd::new(self.open(objid, false)?))
+    }
+
+    fn write_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        Ok(FromStd::new(self.open(objid, true)?))
+    }
+
+    fn rw_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        Ok(FromStd::new(self.open(objid, true)?))
+    }
+
+    fn truncate(&mut self, objid: &Self::Id, size: u64) -> Result<(), Self::Error> {
+        self.open(objid, true)?.set_len(size)
+    }
+
+    fn persist_state(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn load_state(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A `DirStorage` that fails to read one object, to test recovery from IO errors.
+pub struct FaultyStorage {
+    inner: DirStorage,
+    /// The object whose reads fail, if any.
+    pub unreadable: Option<u64>,
+    /// Whether the next `persist_state()` fails.
+    pub fail_persist: bool,
+}
+
+impl FaultyStorage {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: DirStorage::new(root)?,
+            unreadable: None,
+            fail_persist: false,
+        })
+    }
+}
+
+impl PersistentStorage for FaultyStorage {
+    type Id = u64;
+    type Flags = ();
+    type Info = Vec<u8>;
+    type Error = io::Error;
+    type Io<'a> = FromStd<File>;
+
+    fn create(&mut self, objid: &Self::Id, flags: &Self::Flags) -> Result<(), Self::Error> {
+        self.inner.create(objid, flags)
+    }
+
+    fn destroy(&mut self, objid: &Self::Id) -> Result<(), Self::Error> {
+        self.inner.destroy(objid)
+    }
+
+    fn get_info(&mut self, objid: &Self::Id) -> Result<Self::Info, Self::Error> {
+        self.inner.get_info(objid)
+    }
+
+    fn set_info(&mut self, objid: &Self::Id, info: Self::Info) -> Result<(), Self::Error> {
+        self.inner.set_info(objid, info)
+    }
+
+    fn read_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        if self.unreadable == Some(*objid) {
+            return Err(io::Error::other("injected read failure"));
+        }
+        self.inner.read_handle(objid)
+    }
+
+    fn write_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        self.inner.write_handle(objid)
+    }
+
+    fn rw_handle(&mut self, objid: &Self::Id) -> Result<Self::Io<'_>, Self::Error> {
+        self.inner.rw_handle(objid)
+    }
+
+    fn truncate(&mut self, objid: &Self::Id, size: u64) -> Result<(), Self::Error> {
+        self.inner.truncate(objid, size)
+    }
+
+    fn persist_state(&mut self) -> Result<(), Self::Error> {
+        if std::mem::take(&mut self.fail_persist) {
+            return Err(io::Error::other("injected persist failure"));
+        }
+        self.inner.persist_state()
+    }
+
+    fn load_state(&mut self) -> Result<(), Self::Error> {
+        self.inner.load_state()
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use anyhow::Result;
+use common::{copy_dir, read_all, FaultyLethe, FaultyStorage, SimLethe, TestStore, BLOCK_SIZE};
+use embedded_io::blocking::{Read, Write};
+use lethe::{
+    enclave::{Enclave, SimEnclave},
+    error::Error,
+};
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn reopen() -> Result<()> {
+    let store = TestStore::new()?;
+    let enclave = SimEnclave::new();
+
+    {
+        let mut lethe = SimLethe::new(enclave.clone(), store.storage()?);
+        lethe.create(&OBJID, &())?;
+        lethe
+            .write_handle(&OBJID)?
+            .write_all(&['a' as u8; BLOCK_SIZE])?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = SimLethe::new(enclave.clone(), store.storage()?);
+    lethe.load_state()?;
+
+    let mut buf = vec![0; BLOCK_SIZE];
+    lethe.read_handle(&OBJID)?.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &['a' as u8; BLOCK_SIZE]);
+
+    Ok(())
+}
+
+// Restoring an older copy of the storage must be detected, even if the attacker also restores
+// the matching root in the enclave.
+#[test]
+fn rollback() -> Result<()> {
+    let store = TestStore::new()?;
+    let old = TestStore::new()?;
+    let enclave = SimEnclave::new();
+
+    let old_root = {
+        let mut lethe = SimLethe::new(enclave.clone(), store.storage()?);
+        lethe.create(&OBJID, &())?;
+        lethe.persist_state()?;
+        copy_dir(store.path(), old.path())?;
+        let old_root = enclave.raw();
+
+        lethe.destroy(&OBJID)?;
+        lethe.persist_state()?;
+        old_root
+    };
+
+    enclave.set_raw(old_root);
+
+    let mut lethe = SimLethe::new(enclave.clone(), old.storage()?);
+    assert!(matches!(lethe.load_state(), Err(Error::Rollback)));
+
+    Ok(())
+}
+
+// A failed commit doesn't leave its root behind, so retrying doesn't seal over the last committed
+// one.
+#[test]
+fn failed_commit() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = FaultyLethe::new(store.enclave()?, FaultyStorage::new(store.path())?);
+        lethe.create(&OBJID, &())?;
+        lethe.persist_state()?;
+        let committed = lethe.enclave_mut().unseal_roots()?;
+
+        lethe.write_handle(&OBJID)?.write_all(b"data")?;
+        lethe.storage.fail_persist = true;
+        assert!(matches!(lethe.persist_state(), Err(Error::Io)));
+        assert_eq!(lethe.enclave_mut().unseal_roots()?, committed);
+
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = FaultyLethe::new(store.enclave()?, FaultyStorage::new(store.path())?);
+    lethe.load_state()?;
+    assert_eq!(read_all(&mut lethe, OBJID)?, b"data");
+
+    Ok(())
+}
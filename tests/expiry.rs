@@ -0,0 +1,45 @@
+mod common;
+
+use anyhow::Result;
+use common::{DirLethe, TestStore};
+use embedded_io::blocking::Write;
+use lethe::options::ObjectOptions;
+use persistence::PersistentStorage;
+use std::time::{Duration, SystemTime};
+
+const EXPIRED: u64 = 1;
+const FRESH: u64 = 2;
+
+#[test]
+fn expiry() -> Result<()> {
+    let store = TestStore::new()?;
+    let later = SystemTime::now() + Duration::from_secs(3600);
+
+    {
+        // Expired objects bypass the trash.
+        let mut lethe = DirLethe::options()
+            .grace_period(1)
+            .build(store.enclave()?, store.storage()?)?;
+
+        for (objid, when) in [(EXPIRED, SystemTime::UNIX_EPOCH), (FRESH, later)] {
+            lethe.create_with_options(objid, &(), ObjectOptions::new().expiry(when))?;
+            lethe.write_handle(&objid)?.write_all(b"retained")?;
+        }
+        let map_id = lethe.get_khf_mapping(EXPIRED).unwrap().map_id;
+
+        lethe.persist_state()?;
+        assert!(!lethe.exists(EXPIRED));
+        assert!(lethe.trashed().is_empty());
+        assert!(!store.path().join(map_id.to_string()).exists());
+    }
+
+    let mut lethe = store.load()?;
+    assert_eq!(lethe.expiry(FRESH)?, Some(later));
+
+    // The deadline can be moved, and the sweeper can be run ahead of a commit.
+    lethe.set_expiry(FRESH, Some(SystemTime::UNIX_EPOCH))?;
+    assert_eq!(lethe.sweep_expired()?, vec![FRESH]);
+    assert!(!lethe.exists(FRESH));
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, TestStore, BLOCK_SIZE};
+use embedded_io::blocking::Write;
+use lethe::{error::Error, options::ObjectOptions};
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+const FANOUTS: &[u64] = &[2, 2, 2];
+
+#[test]
+fn per_object() -> Result<()> {
+    let store = TestStore::new()?;
+    let data = ['a' as u8; 9 * BLOCK_SIZE];
+
+    {
+        let mut lethe = store.open()?;
+        lethe.create_with_options(OBJID, &(), ObjectOptions::new().khf_fanouts(FANOUTS))?;
+        lethe.write_handle(&OBJID)?.write_all(&data)?;
+        lethe.persist_state()?;
+    }
+
+    // The fanouts are restored along with the object `Khf`.
+    let mut lethe = store.load()?;
+    assert_eq!(lethe.get_khf_mapping(OBJID).unwrap().fanouts, FANOUTS);
+    assert_eq!(lethe.object_stats(OBJID)?.khf_depth, FANOUTS.len() as u64);
+
+    assert_eq!(read_all(&mut lethe, OBJID)?, data);
+
+    Ok(())
+}
+
+#[test]
+fn invalid() -> Result<()> {
+    let store = TestStore::new()?;
+    let mut lethe = store.open()?;
+
+    for fanouts in [&[][..], &[4, 0, 4][..]] {
+        assert!(matches!(
+            lethe.create_with_options(OBJID, &(), ObjectOptions::new().khf_fanouts(fanouts)),
+            Err(Error::InvalidFanouts)
+        ));
+    }
+    assert!(!lethe.exists(OBJID));
+
+    Ok(())
+}
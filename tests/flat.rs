@@ -0,0 +1,36 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, FlatLethe, TestStore, BLOCK_SIZE};
+use embedded_io::blocking::Write;
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn round_trip() -> Result<()> {
+    let store = TestStore::new()?;
+    let data = ['a' as u8; 3 * BLOCK_SIZE + 7];
+
+    {
+        let mut lethe = FlatLethe::new(store.enclave()?, store.storage()?);
+        lethe.create(&OBJID, &())?;
+        lethe.write_handle(&OBJID)?.write_all(&data)?;
+        lethe.persist_state()?;
+
+        // Overwrite a block so that its key is updated before the next commit.
+        lethe
+            .write_handle(&OBJID)?
+            .write_all(&['b' as u8; BLOCK_SIZE])?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = FlatLethe::new(store.enclave()?, store.storage()?);
+    lethe.load_state()?;
+
+    let buf = read_all(&mut lethe, OBJID)?;
+    assert_eq!(&buf[..BLOCK_SIZE], &['b' as u8; BLOCK_SIZE]);
+    assert_eq!(&buf[BLOCK_SIZE..], &data[BLOCK_SIZE..]);
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use anyhow::Result;
+use common::TestStore;
+use persistence::PersistentStorage;
+use std::{fs, path::Path};
+
+const OBJID: u64 = 42;
+const INFO: &[u8] = b"owner=alice mode=0600";
+
+// Returns whether any object in `dir` contains `needle` in the clear.
+fn leaked(dir: &Path, needle: &[u8]) -> Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let contents = fs::read(entry?.path())?;
+        if contents
+            .windows(needle.len())
+            .any(|window| window == needle)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[test]
+fn round_trip() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = store.open()?;
+        lethe.create(&OBJID, &())?;
+        assert_eq!(lethe.get_info(&OBJID)?, Vec::<u8>::new());
+
+        lethe.set_info(&OBJID, INFO.to_vec())?;
+        assert_eq!(lethe.get_info(&OBJID)?, INFO);
+        lethe.persist_state()?;
+    }
+
+    // Nothing is forwarded to, or readable from, the underlying storage.
+    assert!(store.storage()?.get_info(&OBJID)?.is_empty());
+    assert!(!leaked(store.path(), INFO)?);
+
+    let mut lethe = store.load()?;
+    assert_eq!(lethe.get_info(&OBJID)?, INFO);
+
+    Ok(())
+}
+
+#[test]
+fn destroy() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = store.open()?;
+    lethe.create(&OBJID, &())?;
+    lethe.set_info(&OBJID, INFO.to_vec())?;
+    lethe.persist_state()?;
+
+    let meta_id = lethe.get_khf_mapping(OBJID).unwrap().meta_id;
+    lethe.destroy(&OBJID)?;
+    lethe.persist_state()?;
+
+    assert!(!store.path().join(meta_id.to_string()).exists());
+    assert!(lethe.get_info(&OBJID).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use allocator::seq::SequentialAllocator;
+use anyhow::Result;
+use common::{read_all, DirLethe, TestStore};
+use embedded_io::blocking::Write;
+use lethe::{
+    error::Error,
+    namespace::{EntryKind, Namespace},
+};
+use persistence::PersistentStorage;
+
+const SUPERBLOCK: u64 = 0;
+
+#[test]
+fn namespace() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut ns = Namespace::create(
+            store.open()?,
+            SequentialAllocator::<u64>::default(),
+            SUPERBLOCK,
+        )?;
+
+        ns.mkdir("/logs")?;
+        let objid = ns.create_object("/logs/app.log", &())?;
+        ns.storage().write_handle(&objid)?.write_all(b"hello")?;
+
+        assert!(matches!(
+            ns.create_object("/logs/app.log", &()),
+            Err(Error::ObjectExists)
+        ));
+        assert!(matches!(
+            ns.create_object("/missing/app.log", &()),
+            Err(Error::MissingName)
+        ));
+        assert!(matches!(
+            ns.mkdir("/logs/app.log/nested"),
+            Err(Error::NotADirectory)
+        ));
+
+        ns.into_inner().persist_state()?;
+    }
+
+    let mut ns = Namespace::<_, SequentialAllocator<u64>>::open(store.load()?, SUPERBLOCK)?;
+
+    let entries = ns.readdir("/logs")?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "app.log");
+    assert_eq!(entries[0].kind, EntryKind::File);
+
+    let objid = ns.lookup("logs//app.log")?;
+    assert_eq!(read_all(ns.storage(), objid)?, b"hello");
+
+    // Directories have to be emptied before they can be unlinked.
+    assert!(matches!(ns.unlink("/logs"), Err(Error::DirectoryNotEmpty)));
+    ns.unlink("/logs/app.log")?;
+    ns.unlink("/logs")?;
+
+    assert!(ns.readdir("/")?.is_empty());
+    assert!(ns.storage().get_khf_mapping(objid).is_none());
+
+    Ok(())
+}
+
+// Each change is committed whole, even when every operation it's made of is due a commit.
+#[test]
+fn auto_persist() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let lethe = DirLethe::options()
+        .persist_after_ops(1)
+        .build(store.enclave()?, store.storage()?)?;
+    let mut ns = Namespace::create(lethe, SequentialAllocator::<u64>::default(), SUPERBLOCK)?;
+    let epoch = ns.storage().epoch();
+
+    ns.mkdir("/logs")?;
+    assert_eq!(ns.storage().epoch(), epoch + 1);
+    ns.create_object("/logs/app.log", &())?;
+    assert_eq!(ns.storage().epoch(), epoch + 2);
+    ns.unlink("/logs/app.log")?;
+    assert_eq!(ns.storage().epoch(), epoch + 3);
+
+    Ok(())
+}
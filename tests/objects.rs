@@ -0,0 +1,54 @@
+mod common;
+
+use anyhow::Result;
+use common::TestStore;
+use embedded_io::blocking::Write;
+use persistence::PersistentStorage;
+
+#[test]
+fn enumerate() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = store.open()?;
+        for objid in [30, 10, 20] {
+            lethe.create(&objid, &())?;
+            lethe
+                .write_handle(&objid)?
+                .write_all(&vec![0xab; objid as usize])?;
+        }
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = store.load()?;
+
+    // Existence checks don't load anything.
+    assert!(lethe.exists(10));
+    assert!(!lethe.exists(15));
+    assert_eq!(lethe.stats()?.cached_khfs, 0);
+    assert_eq!(lethe.stats()?.cached_metadata, 0);
+
+    let objects = lethe.objects()?;
+    assert_eq!(
+        objects
+            .iter()
+            .map(|o| (o.objid, o.size))
+            .collect::<Vec<_>>(),
+        vec![(10, 10), (20, 20), (30, 30)]
+    );
+    let mapping = lethe.get_khf_mapping(20).unwrap();
+    assert_eq!(objects[1].map_id, mapping.map_id);
+    assert_eq!(objects[1].khf_id, mapping.khf_id);
+
+    let ranged = lethe
+        .iter_objects(15..)
+        .map(|o| o.map(|o| o.objid))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(ranged, vec![20, 30]);
+    assert_eq!(lethe.stats()?.cached_khfs, 0);
+
+    // Enumerating doesn't fill the metadata cache either.
+    assert_eq!(lethe.stats()?.cached_metadata, 0);
+
+    Ok(())
+}
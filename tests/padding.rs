@@ -0,0 +1,73 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, DirLethe, TestStore};
+use embedded_io::{
+    blocking::{Read, Seek, Write},
+    SeekFrom,
+};
+use lethe::options::PaddingPolicy;
+use persistence::PersistentStorage;
+use std::fs;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn power_of_two() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let map_id = {
+        let mut lethe = DirLethe::options()
+            .padding(PaddingPolicy::PowerOfTwo)
+            .build(store.enclave()?, store.storage()?)?;
+        lethe.create(&OBJID, &())?;
+        lethe.write_handle(&OBJID)?.write_all(&['a' as u8; 5000])?;
+        lethe.persist_state()?;
+        lethe.get_khf_mapping(OBJID).unwrap().map_id
+    };
+    let path = store.path().join(map_id.to_string());
+    assert_eq!(fs::metadata(&path)?.len(), 8192);
+
+    // The policy is loaded along with the store.
+    let mut lethe = store.load()?;
+
+    // The logical length survives a reopen, and reads stop there.
+    let mut io = lethe.read_handle(&OBJID)?;
+    assert_eq!(io.seek(SeekFrom::End(0))?, 5000);
+    io.seek(SeekFrom::Start(4096))?;
+    let mut buf = vec![0; 4096];
+    assert_eq!(io.read(&mut buf)?, 904);
+    drop(io);
+
+    // Truncating keeps the padding.
+    lethe.truncate(&OBJID, 100)?;
+    assert_eq!(fs::metadata(&path)?.len(), 128);
+
+    assert_eq!(read_all(&mut lethe, OBJID)?, vec!['a' as u8; 100]);
+
+    Ok(())
+}
+
+#[test]
+fn fixed() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .padding(PaddingPolicy::Fixed(1 << 16))
+        .build(store.enclave()?, store.storage()?)?;
+
+    // Empty objects look just like full ones.
+    lethe.create(&OBJID, &())?;
+    let path = store
+        .path()
+        .join(lethe.get_khf_mapping(OBJID).unwrap().map_id.to_string());
+    assert_eq!(fs::metadata(&path)?.len(), 1 << 16);
+    assert_eq!(lethe.read_handle(&OBJID)?.seek(SeekFrom::End(0))?, 0);
+
+    // Extending by truncation reads back as zeros.
+    lethe.truncate(&OBJID, 10)?;
+    assert_eq!(read_all(&mut lethe, OBJID)?, vec![0; 10]);
+    assert_eq!(fs::metadata(&path)?.len(), 1 << 16);
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, DirLethe, TestStore};
+use embedded_io::blocking::Write;
+use lethe::error::Error;
+use persistence::PersistentStorage;
+use std::fs;
+
+const A: u64 = 1;
+const B: u64 = 2;
+const C: u64 = 3;
+
+fn setup(lethe: &mut DirLethe) -> Result<()> {
+    for (objid, data) in [(A, b"a"), (B, b"b")] {
+        lethe.create(&objid, &())?;
+        lethe.write_handle(&objid)?.write_all(data)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn rename() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = store.open()?;
+        setup(&mut lethe)?;
+
+        lethe.rename(A, C, false)?;
+        assert!(lethe.get_khf_mapping(A).is_none());
+        assert_eq!(read_all(&mut lethe, C)?, b"a");
+
+        // Replacing an existing target needs to be asked for.
+        assert!(matches!(
+            lethe.rename(C, B, false),
+            Err(Error::ObjectExists)
+        ));
+
+        let objects = fs::read_dir(store.path())?.count();
+        lethe.rename(C, B, true)?;
+        assert_eq!(fs::read_dir(store.path())?.count(), objects - 3);
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = store.load()?;
+    assert_eq!(read_all(&mut lethe, B)?, b"a");
+    assert!(lethe.get_khf_mapping(C).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn exchange() -> Result<()> {
+    let store = TestStore::new()?;
+    let mut lethe = store.open()?;
+    setup(&mut lethe)?;
+
+    lethe.exchange(A, B)?;
+    assert_eq!(read_all(&mut lethe, A)?, b"b");
+    assert_eq!(read_all(&mut lethe, B)?, b"a");
+    assert!(matches!(lethe.exchange(A, C), Err(Error::MissingKhf)));
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, TestStore, BLOCK_SIZE};
+use embedded_io::{
+    blocking::{Read, Seek, Write},
+    SeekFrom,
+};
+use kms::KeyManagementScheme;
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn shred_range() -> Result<()> {
+    let store = TestStore::new()?;
+
+    // Shred from the middle of block 0 to the middle of block 3.
+    let (start, end) = (100, 3 * BLOCK_SIZE + 50);
+
+    {
+        let mut lethe = store.open()?;
+        lethe.create(&OBJID, &())?;
+        lethe
+            .write_handle(&OBJID)?
+            .write_all(&['a' as u8; 4 * BLOCK_SIZE])?;
+        lethe.persist_state()?;
+
+        let khf = lethe.get_khf_mut(OBJID)?.unwrap();
+        let old_keys = [
+            khf.derive(0)?,
+            khf.derive(1)?,
+            khf.derive(2)?,
+            khf.derive(3)?,
+        ];
+
+        lethe.shred_range(OBJID, start as u64, (end - start) as u64)?;
+        lethe.persist_state()?;
+
+        // Every block touched by the range is under a fresh key.
+        let khf = lethe.get_khf_mut(OBJID)?.unwrap();
+        for (block, old_key) in old_keys.iter().enumerate() {
+            assert_ne!(&khf.derive(block as u64)?, old_key);
+        }
+    }
+
+    let mut lethe = store.load()?;
+
+    let buf = read_all(&mut lethe, OBJID)?;
+    assert_eq!(buf.len(), 4 * BLOCK_SIZE);
+    assert_eq!(&buf[..start], &vec!['a' as u8; start][..]);
+    assert_eq!(&buf[start..end], &vec![0; end - start][..]);
+    assert_eq!(&buf[end..], &vec!['a' as u8; 4 * BLOCK_SIZE - end][..]);
+
+    Ok(())
+}
+
+#[test]
+fn rewrite_shredded() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = store.open()?;
+    lethe.create(&OBJID, &())?;
+    lethe
+        .write_handle(&OBJID)?
+        .write_all(&['a' as u8; 2 * BLOCK_SIZE])?;
+    lethe.shred_range(OBJID, 0, 2 * BLOCK_SIZE as u64)?;
+
+    let mut io = lethe.write_handle(&OBJID)?;
+    io.seek(SeekFrom::Start(10))?;
+    io.write_all(&['b' as u8; 10])?;
+
+    let mut buf = vec![1; 2 * BLOCK_SIZE];
+    io.seek(SeekFrom::Start(0))?;
+    io.read_exact(&mut buf)?;
+    assert_eq!(&buf[..10], &[0; 10]);
+    assert_eq!(&buf[10..20], &['b' as u8; 10]);
+    assert_eq!(&buf[20..], &vec![0; 2 * BLOCK_SIZE - 20][..]);
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, TestStore, BLOCK_SIZE};
+use embedded_io::blocking::{Read, Write};
+use lethe::error::Error;
+use persistence::PersistentStorage;
+use std::fs;
+
+const OBJID: u64 = 42;
+const OTHER: u64 = 43;
+
+#[test]
+fn isolation() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = store.open()?;
+    lethe.create(&OBJID, &())?;
+    lethe
+        .write_handle(&OBJID)?
+        .write_all(&['a' as u8; BLOCK_SIZE])?;
+
+    let snapshot = lethe.snapshot(OBJID)?;
+    lethe
+        .write_handle(&OBJID)?
+        .write_all(&['b' as u8; 2 * BLOCK_SIZE])?;
+    lethe.persist_state()?;
+
+    // The snapshot still sees the old version, at its old length.
+    let mut buf = vec![];
+    lethe
+        .snapshot_handle(snapshot, OBJID)?
+        .read_to_end(&mut buf)?;
+    assert_eq!(buf, vec!['a' as u8; BLOCK_SIZE]);
+
+    assert_eq!(
+        read_all(&mut lethe, OBJID)?,
+        vec!['b' as u8; 2 * BLOCK_SIZE]
+    );
+
+    // Releasing the snapshot destroys its copy.
+    let objects = fs::read_dir(store.path())?.count();
+    lethe.release_snapshot(snapshot)?;
+    assert_eq!(fs::read_dir(store.path())?.count(), objects - 1);
+    assert!(matches!(
+        lethe.snapshot_handle(snapshot, OBJID),
+        Err(Error::MissingSnapshot)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn whole_store() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let objects = {
+        let mut lethe = store.open()?;
+        for objid in [OBJID, OTHER] {
+            lethe.create(&objid, &())?;
+            lethe
+                .write_handle(&objid)?
+                .write_all(&[objid as u8; BLOCK_SIZE])?;
+        }
+        lethe.persist_state()?;
+        let objects = fs::read_dir(store.path())?.count();
+
+        let snapshot = lethe.snapshot_all()?;
+        lethe.destroy(&OTHER)?;
+
+        for objid in [OBJID, OTHER] {
+            let mut buf = vec![];
+            lethe
+                .snapshot_handle(snapshot, objid)?
+                .read_to_end(&mut buf)?;
+            assert_eq!(buf, vec![objid as u8; BLOCK_SIZE]);
+        }
+
+        objects
+    };
+
+    // Snapshots don't survive a reload, and neither do their copies.
+    store.load()?;
+    assert_eq!(fs::read_dir(store.path())?.count(), objects - 3);
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, TestStore, BLOCK_SIZE};
+use embedded_io::{
+    blocking::{Seek, Write},
+    SeekFrom,
+};
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn write_past_end() -> Result<()> {
+    let store = TestStore::new()?;
+
+    // Data in block 0 and block 3, with blocks 1 and 2 never written.
+    let offset = 3 * BLOCK_SIZE + 10;
+
+    {
+        let mut lethe = store.open()?;
+        lethe.create(&OBJID, &())?;
+
+        let mut io = lethe.write_handle(&OBJID)?;
+        io.write_all(&['a' as u8; 100])?;
+        io.seek(SeekFrom::Start(offset as u64))?;
+        io.write_all(&['b' as u8; 10])?;
+        drop(io);
+
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = store.load()?;
+
+    let buf = read_all(&mut lethe, OBJID)?;
+    assert_eq!(buf.len(), offset + 10);
+    assert_eq!(&buf[..100], &['a' as u8; 100]);
+    assert_eq!(&buf[100..offset], &vec![0; offset - 100][..]);
+    assert_eq!(&buf[offset..], &['b' as u8; 10]);
+
+    assert_eq!(lethe.seek_data(OBJID, 0)?, Some(0));
+    assert_eq!(lethe.seek_hole(OBJID, 0)?, Some(BLOCK_SIZE as u64));
+    assert_eq!(
+        lethe.seek_data(OBJID, BLOCK_SIZE as u64)?,
+        Some(3 * BLOCK_SIZE as u64)
+    );
+    assert_eq!(
+        lethe.seek_hole(OBJID, 3 * BLOCK_SIZE as u64)?,
+        Some(offset as u64 + 10)
+    );
+    assert_eq!(lethe.seek_data(OBJID, offset as u64 + 10)?, None);
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use anyhow::Result;
+use common::{DirLethe, DirStorage, NestedLethe, BLOCK_SIZE};
+use embedded_io::{
+    blocking::{Read, Seek, Write},
+    SeekFrom,
+};
+use persistence::PersistentStorage;
+use std::path::Path;
+use tempfile::TempDir;
+
+const OBJID: u64 = 42;
+
+fn open(dir: &Path) -> Result<NestedLethe> {
+    let inner = DirLethe::new(
+        common::enclave(dir.join("inner.enclave"))?,
+        DirStorage::new(dir.join("objects"))?,
+    );
+    Ok(NestedLethe::new(
+        common::enclave(dir.join("outer.enclave"))?,
+        inner,
+    ))
+}
+
+// Writes through the outer instance, commits, and reads the data back after reopening both.
+#[test]
+fn round_trip() -> Result<()> {
+    let dir = TempDir::new()?;
+    let data = ['a' as u8; 3 * BLOCK_SIZE + 7];
+
+    {
+        let mut lethe = open(dir.path())?;
+        lethe.create(&OBJID, &())?;
+        lethe.write_handle(&OBJID)?.write_all(&data)?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = open(dir.path())?;
+    lethe.load_state()?;
+
+    let mut buf = vec![0; data.len()];
+    lethe.read_handle(&OBJID)?.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &data[..]);
+
+    Ok(())
+}
+
+// Commits of the outer instance are picked up across several epochs of the inner instance.
+#[test]
+fn repeated_commits() -> Result<()> {
+    let dir = TempDir::new()?;
+
+    {
+        let mut lethe = open(dir.path())?;
+        lethe.create(&OBJID, &())?;
+
+        for c in ['a', 'b', 'c'] {
+            lethe.write_handle(&OBJID)?.write_all(&[c as u8; BLOCK_SIZE])?;
+            lethe.persist_state()?;
+        }
+    }
+
+    let mut lethe = open(dir.path())?;
+    lethe.load_state()?;
+
+    let mut buf = vec![0; BLOCK_SIZE];
+    lethe.read_handle(&OBJID)?.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &['c' as u8; BLOCK_SIZE]);
+
+    Ok(())
+}
+
+#[test]
+fn truncate() -> Result<()> {
+    let dir = TempDir::new()?;
+
+    {
+        let mut lethe = open(dir.path())?;
+        lethe.create(&OBJID, &())?;
+        lethe.write_handle(&OBJID)?.write_all(&['a' as u8; 2 * BLOCK_SIZE])?;
+        lethe.truncate(&OBJID, BLOCK_SIZE as u64 + 3)?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = open(dir.path())?;
+    lethe.load_state()?;
+
+    let mut io = lethe.read_handle(&OBJID)?;
+    assert_eq!(io.seek(SeekFrom::End(0))?, BLOCK_SIZE as u64 + 3);
+
+    let mut buf = vec![0; BLOCK_SIZE + 3];
+    io.seek(SeekFrom::Start(0))?;
+    io.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &['a' as u8; BLOCK_SIZE + 3][..]);
+
+    Ok(())
+}
+
+#[test]
+fn destroy() -> Result<()> {
+    let dir = TempDir::new()?;
+
+    {
+        let mut lethe = open(dir.path())?;
+        lethe.create(&OBJID, &())?;
+        lethe.write_handle(&OBJID)?.write_all(&['a' as u8; BLOCK_SIZE])?;
+        lethe.persist_state()?;
+        lethe.destroy(&OBJID)?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = open(dir.path())?;
+    lethe.load_state()?;
+    assert!(lethe.read_handle(&OBJID).is_err());
+
+    Ok(())
+}
+
+// The outer instance can be built on an inner instance that's already been loaded.
+#[test]
+fn loaded_inner() -> Result<()> {
+    let dir = TempDir::new()?;
+
+    {
+        let mut lethe = open(dir.path())?;
+        lethe.create(&OBJID, &())?;
+        lethe.write_handle(&OBJID)?.write_all(&['a' as u8; BLOCK_SIZE])?;
+        lethe.persist_state()?;
+    }
+
+    let mut inner = DirLethe::new(
+        common::enclave(dir.path().join("inner.enclave"))?,
+        DirStorage::new(dir.path().join("objects"))?,
+    );
+    inner.load_state()?;
+
+    let mut lethe = NestedLethe::new(common::enclave(dir.path().join("outer.enclave"))?, inner);
+    lethe.load_state()?;
+
+    let mut buf = vec![0; BLOCK_SIZE];
+    lethe.read_handle(&OBJID)?.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &['a' as u8; BLOCK_SIZE]);
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use anyhow::Result;
+use common::{DirLethe, TestStore, BLOCK_SIZE};
+use embedded_io::blocking::Write;
+use lethe::{error::Error, options::PaddingPolicy};
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn stats() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = store.open()?;
+        lethe.create(&OBJID, &())?;
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = store.load()?;
+
+    let stats = lethe.stats()?;
+    assert_eq!(stats.objects, 1);
+    assert_eq!(stats.cached_khfs, 0);
+    assert!(stats.master_khf_keys > 0);
+    assert!(stats.master_khf_size > 0);
+
+    // Querying an object caches its `Khf`, but reports whether it was cached beforehand.
+    assert!(!lethe.object_stats(OBJID)?.cached);
+    assert_eq!(lethe.stats()?.cached_khfs, 1);
+    assert!(lethe.object_stats(OBJID)?.cached);
+
+    assert!(matches!(
+        lethe.object_stats(OBJID + 1),
+        Err(Error::MissingKhf)
+    ));
+
+    Ok(())
+}
+
+// Block counts follow the logical length, whatever the padding.
+#[test]
+fn padded_blocks() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .padding(PaddingPolicy::Fixed(16 * BLOCK_SIZE as u64))
+        .build(store.enclave()?, store.storage()?)?;
+    lethe.create(&OBJID, &())?;
+    assert_eq!(lethe.object_stats(OBJID)?.blocks, 0);
+
+    lethe
+        .write_handle(&OBJID)?
+        .write_all(&['a' as u8; BLOCK_SIZE + 1])?;
+    assert_eq!(lethe.object_stats(OBJID)?.blocks, 2);
+
+    Ok(())
+}
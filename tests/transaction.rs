@@ -0,0 +1,115 @@
+mod common;
+
+use anyhow::Result;
+use common::{enclave, read_all, FaultyLethe, FaultyStorage, TestStore};
+use embedded_io::blocking::{Read, Write};
+use lethe::error::Error;
+use persistence::PersistentStorage;
+use tempfile::TempDir;
+
+const INDEX: u64 = 1;
+const DATA: u64 = 2;
+const BROKEN: u64 = 3;
+
+#[test]
+fn commit() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = store.open()?;
+        let epoch = lethe.epoch();
+
+        let mut txn = lethe.transaction();
+        txn.create(INDEX, ())
+            .create(DATA, ())
+            .write(DATA, 0, b"hello world")
+            .write(INDEX, 0, b"hello: 0..11");
+        txn.commit()?;
+
+        // Everything lands in a single epoch.
+        assert_eq!(lethe.epoch(), epoch + 1);
+    }
+
+    let mut lethe = store.load()?;
+    assert_eq!(read_all(&mut lethe, INDEX)?, b"hello: 0..11");
+    assert_eq!(read_all(&mut lethe, DATA)?, b"hello world");
+
+    Ok(())
+}
+
+#[test]
+fn all_or_nothing() -> Result<()> {
+    let store = TestStore::new()?;
+    let mut lethe = store.open()?;
+
+    let mut txn = lethe.transaction();
+    txn.create(INDEX, ()).write(INDEX, 0, b"old");
+    txn.commit()?;
+
+    // The destroy of a missing object fails the whole transaction.
+    let mut txn = lethe.transaction();
+    txn.write(INDEX, 0, b"new")
+        .create(DATA, ())
+        .destroy(DATA)
+        .destroy(DATA);
+    assert!(matches!(txn.commit(), Err(Error::MissingKhf)));
+
+    assert_eq!(read_all(&mut lethe, INDEX)?, b"old");
+    assert!(lethe.get_khf_mapping(DATA).is_none());
+
+    // Dropped transactions aren't applied either.
+    let mut txn = lethe.transaction();
+    txn.write(INDEX, 0, b"new");
+    drop(txn);
+    assert_eq!(read_all(&mut lethe, INDEX)?, b"old");
+
+    Ok(())
+}
+
+// An IO error partway through leaves every object as it was, and what was changed before the
+// transaction is still committed.
+#[test]
+fn io_error() -> Result<()> {
+    let dir = TempDir::new()?;
+    let enclave_dir = TempDir::new()?;
+    let enclave_path = enclave_dir.path().join("enclave");
+
+    {
+        let mut lethe = FaultyLethe::new(enclave(&enclave_path)?, FaultyStorage::new(dir.path())?);
+        for objid in [INDEX, BROKEN] {
+            lethe.create(&objid, &())?;
+            lethe.write_handle(&objid)?.write_all(b"old")?;
+        }
+        lethe.persist_state()?;
+        lethe.set_info(&INDEX, b"info".to_vec())?;
+
+        // Copying the object to write it fails, after the other writes went through.
+        lethe.storage.unreadable = Some(lethe.get_khf_mapping(BROKEN).unwrap().map_id);
+        let mut txn = lethe.transaction();
+        txn.write(INDEX, 0, b"new")
+            .create(DATA, ())
+            .write(DATA, 0, b"data")
+            .write(BROKEN, 0, b"new")
+            .destroy(INDEX);
+        assert!(matches!(txn.commit(), Err(Error::Io)));
+        lethe.storage.unreadable = None;
+
+        assert_eq!(lethe.objects()?.len(), 2);
+        let mut buf = vec![];
+        lethe.read_handle(&INDEX)?.read_to_end(&mut buf)?;
+        assert_eq!(buf, b"old");
+    }
+
+    let mut lethe = FaultyLethe::new(enclave(&enclave_path)?, FaultyStorage::new(dir.path())?);
+    lethe.load_state()?;
+    assert_eq!(lethe.get_info(&INDEX)?, b"info");
+    assert!(!lethe.exists(DATA));
+
+    for objid in [INDEX, BROKEN] {
+        let mut buf = vec![];
+        lethe.read_handle(&objid)?.read_to_end(&mut buf)?;
+        assert_eq!(buf, b"old");
+    }
+
+    Ok(())
+}
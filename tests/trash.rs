@@ -0,0 +1,62 @@
+mod common;
+
+use anyhow::Result;
+use common::{read_all, DirLethe, TestStore};
+use embedded_io::blocking::Write;
+use lethe::error::Error;
+use persistence::PersistentStorage;
+
+const OBJID: u64 = 42;
+
+#[test]
+fn restore() -> Result<()> {
+    let store = TestStore::new()?;
+
+    {
+        let mut lethe = DirLethe::options()
+            .grace_period(1)
+            .build(store.enclave()?, store.storage()?)?;
+        lethe.create(&OBJID, &())?;
+        lethe.write_handle(&OBJID)?.write_all(b"precious")?;
+        lethe.destroy(&OBJID)?;
+        assert!(!lethe.exists(OBJID));
+        assert_eq!(lethe.trashed(), vec![OBJID]);
+
+        // The trash survives one commit.
+        lethe.persist_state()?;
+    }
+
+    let mut lethe = DirLethe::options()
+        .grace_period(1)
+        .build(store.enclave()?, store.storage()?)?;
+    lethe.load_state()?;
+    lethe.restore(OBJID)?;
+    assert!(matches!(lethe.restore(OBJID), Err(Error::MissingTrash)));
+    assert_eq!(read_all(&mut lethe, OBJID)?, b"precious");
+
+    Ok(())
+}
+
+#[test]
+fn expiry() -> Result<()> {
+    let store = TestStore::new()?;
+
+    let mut lethe = DirLethe::options()
+        .grace_period(1)
+        .build(store.enclave()?, store.storage()?)?;
+    lethe.create(&OBJID, &())?;
+    lethe.write_handle(&OBJID)?.write_all(b"precious")?;
+    let map_id = lethe.get_khf_mapping(OBJID).unwrap().map_id;
+    lethe.destroy(&OBJID)?;
+
+    lethe.persist_state()?;
+    assert!(store.path().join(map_id.to_string()).exists());
+
+    // Once the grace period is over, the object is gone for good.
+    lethe.persist_state()?;
+    assert!(lethe.trashed().is_empty());
+    assert!(!store.path().join(map_id.to_string()).exists());
+    assert!(matches!(lethe.restore(OBJID), Err(Error::MissingTrash)));
+
+    Ok(())
+}